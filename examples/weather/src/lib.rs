@@ -9,11 +9,26 @@ use urlencoding::encode;
 const FETCH_GAS: Gas = Gas::from_tgas(40);
 const CALLBACK_GAS: Gas = Gas::from_tgas(20);
 
+/// Fields of the OpenWeather `find` response the relayer extracts, so only these are uploaded.
+const CITY_POINTER: &str = "/list/0/name";
+const COUNTRY_POINTER: &str = "/list/0/sys/country";
+const TEMPERATURE_POINTER: &str = "/list/0/main/temp";
+const DESCRIPTION_POINTER: &str = "/list/0/weather/0/description";
+
 #[near(serializers = [json])]
 #[derive(Clone)]
 pub enum FetchStatus {
     Completed,
     TimedOut,
+    Failed { kind: String, message: String },
+    Cancelled,
+}
+
+#[near(serializers = [json])]
+#[derive(Clone)]
+pub struct HttpHeader {
+    pub name: String,
+    pub value: String,
 }
 
 #[near(serializers = [json])]
@@ -22,15 +37,30 @@ pub struct FetchResult {
     pub request_id: u64,
     pub url: String,
     pub status: FetchStatus,
+    pub status_code: Option<u16>,
+    #[serde(default)]
+    pub headers: Vec<HttpHeader>,
     pub body: Option<Vec<u8>>,
     pub context: Option<Vec<u8>>,
     pub caller: AccountId,
 }
 
+#[near(serializers = [json])]
+#[derive(Clone, Default)]
+pub struct FetchOptions {
+    pub extract: Option<Vec<String>>,
+}
+
 #[ext_contract(http_fetcher)]
 #[allow(dead_code)]
 trait HttpFetcher {
-    fn fetch(&mut self, url: String, context: Option<Vec<u8>>) -> FetchResult;
+    fn fetch(
+        &mut self,
+        url: String,
+        context: Option<Vec<u8>>,
+        request: Option<Value>,
+        options: Option<FetchOptions>,
+    ) -> FetchResult;
 }
 
 #[derive(BorshSerialize, BorshDeserialize, BorshStorageKey)]
@@ -55,14 +85,23 @@ impl Contract {
         }
     }
 
+    /// The attached deposit is forwarded to the fetcher to pay its request fee.
+    #[payable]
     pub fn request_weather(&mut self, city: String) -> Promise {
         let encoded_city = encode(&city);
+        // The relayer fills in the API key, so it never appears on chain.
         let url = format!(
-            "https://api.openweathermap.org/data/2.5/find?q={encoded_city}&appid=5796abbde9106b7da4febfae8c44c232&units=metric"
+            "https://api.openweathermap.org/data/2.5/find?q={encoded_city}&appid={{{{secret:openweather}}}}&units=metric"
         );
         http_fetcher::ext(self.fetcher_account.clone())
             .with_static_gas(FETCH_GAS)
-            .fetch(url, Some(city.as_bytes().to_vec()))
+            .with_attached_deposit(env::attached_deposit())
+            .fetch(
+                url,
+                Some(city.as_bytes().to_vec()),
+                None,
+                Some(weather_fetch_options()),
+            )
             .then(
                 Self::ext(env::current_account_id())
                     .with_static_gas(CALLBACK_GAS)
@@ -79,6 +118,11 @@ impl Contract {
         match result {
             Ok(fetch_result) => match fetch_result.status {
                 FetchStatus::Completed => {
+                    let status_code = fetch_result.status_code.unwrap_or(200);
+                    if !(200..300).contains(&status_code) {
+                        env::log_str(&format!("Weather API returned HTTP {status_code}"));
+                        return None;
+                    }
                     if let Some(body) = fetch_result.body {
                         if let Some(message) = format_weather_message(&body) {
                             env::log_str(&message);
@@ -97,6 +141,14 @@ impl Contract {
                     env::log_str("Fetch timed out");
                     None
                 }
+                FetchStatus::Failed { kind, message } => {
+                    env::log_str(&format!("Fetch failed ({kind}): {message}"));
+                    None
+                }
+                FetchStatus::Cancelled => {
+                    env::log_str("Fetch cancelled");
+                    None
+                }
             },
             Err(_) => {
                 env::log_str("Fetch promise failed");
@@ -118,26 +170,33 @@ impl Default for Contract {
     }
 }
 
+fn weather_fetch_options() -> FetchOptions {
+    FetchOptions {
+        extract: Some(
+            [
+                CITY_POINTER,
+                COUNTRY_POINTER,
+                TEMPERATURE_POINTER,
+                DESCRIPTION_POINTER,
+            ]
+            .map(String::from)
+            .to_vec(),
+        ),
+    }
+}
+
+/// Format the object the relayer extracted, which maps each requested pointer to its value.
 fn format_weather_message(body: &[u8]) -> Option<String> {
-    let value: Value = serde_json::from_slice(body).ok()?;
-    let list = value.get("list")?.as_array()?;
-    let first = list.first()?;
-
-    let city = first.get("name")?.as_str()?.to_string();
-    let country = first
-        .get("sys")
-        .and_then(|sys| sys.get("country"))
+    let fields: Value = serde_json::from_slice(body).ok()?;
+
+    let city = fields.get(CITY_POINTER)?.as_str()?.to_string();
+    let country = fields
+        .get(COUNTRY_POINTER)
         .and_then(|c| c.as_str())
         .map(|s| s.to_string());
-    let temperature_c = first
-        .get("main")
-        .and_then(|main| main.get("temp"))
-        .and_then(|t| t.as_f64());
-    let description = first
-        .get("weather")
-        .and_then(|arr| arr.as_array())
-        .and_then(|arr| arr.first())
-        .and_then(|entry| entry.get("description"))
+    let temperature_c = fields.get(TEMPERATURE_POINTER).and_then(|t| t.as_f64());
+    let description = fields
+        .get(DESCRIPTION_POINTER)
         .and_then(|d| d.as_str())
         .map(|s| s.to_string());
 
@@ -176,4 +235,13 @@ mod tests {
         let contract = Contract::new(account("fetcher.testnet"));
         assert_eq!(contract.fetcher_account, account("fetcher.testnet"));
     }
+
+    #[test]
+    fn formats_extracted_fields() {
+        let body = br#"{"/list/0/main/temp":21.34,"/list/0/name":"Barcelona","/list/0/sys/country":null,"/list/0/weather/0/description":"clear sky"}"#;
+        assert_eq!(
+            format_weather_message(body).as_deref(),
+            Some("Weather in Barcelona is 21.3C (clear sky)")
+        );
+    }
 }
@@ -0,0 +1,500 @@
+//! Follow final blocks over JSON-RPC and pick the contract's `http_fetch` events out of the
+//! transactions and receipts addressed to it, so requests can be dispatched as soon as they land
+//! on chain.
+
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Context, Result};
+use near_api::types::AccountId;
+use reqwest::Client;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::{debug, trace, warn};
+
+const EVENT_PREFIX: &str = "EVENT_JSON:";
+const EVENT_STANDARD: &str = "http_fetch";
+
+/// Contract methods whose receipts can emit events we care about.
+const WATCHED_METHODS: &[&str] = &["fetch", "cancel"];
+
+/// Upper bound on blocks walked per `poll`, so a relayer that fell far behind catches up in
+/// steps instead of stalling; the reconciliation poll covers anything skipped.
+const MAX_BLOCKS_PER_POLL: u64 = 100;
+
+/// How many blocks a receipt's outcome is looked up for before giving up on it.
+const RECEIPT_LOOKUP_BLOCKS: u64 = 20;
+
+/// How many blocks a watched receipt id is remembered, so a receipt found both in a chunk and
+/// through the transaction it was converted from is only reported once.
+const SEEN_RECEIPT_BLOCKS: u64 = 2 * RECEIPT_LOOKUP_BLOCKS;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FetchEvent {
+    Requested { request_id: u64 },
+    Cancelled { request_id: u64 },
+}
+
+impl FetchEvent {
+    pub fn request_id(&self) -> u64 {
+        match self {
+            FetchEvent::Requested { request_id } | FetchEvent::Cancelled { request_id } => {
+                *request_id
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct EventLog {
+    standard: String,
+    event: String,
+    #[serde(default)]
+    data: Vec<EventData>,
+}
+
+#[derive(Deserialize)]
+struct EventData {
+    request_id: u64,
+}
+
+/// Parse one `EVENT_JSON:` log line emitted by the fetch contract. Logs from other standards
+/// and events the relayer doesn't react to yield nothing.
+pub fn parse_event_log(log: &str) -> Vec<FetchEvent> {
+    let Some(payload) = log.strip_prefix(EVENT_PREFIX) else {
+        return Vec::new();
+    };
+    let Ok(event) = serde_json::from_str::<EventLog>(payload) else {
+        return Vec::new();
+    };
+    if event.standard != EVENT_STANDARD {
+        return Vec::new();
+    }
+
+    event
+        .data
+        .into_iter()
+        .filter_map(|data| match event.event.as_str() {
+            "fetch_request" => Some(FetchEvent::Requested {
+                request_id: data.request_id,
+            }),
+            "fetch_cancelled" => Some(FetchEvent::Cancelled {
+                request_id: data.request_id,
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+#[derive(Deserialize)]
+struct RpcResponse<T> {
+    result: Option<T>,
+    error: Option<RpcError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcError {
+    #[serde(default)]
+    cause: Option<RpcErrorCause>,
+    #[serde(default)]
+    data: Option<Value>,
+    #[serde(default)]
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcErrorCause {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct BlockView {
+    header: BlockHeaderView,
+    chunks: Vec<ChunkHeaderView>,
+}
+
+#[derive(Deserialize)]
+struct BlockHeaderView {
+    height: u64,
+    hash: String,
+}
+
+#[derive(Deserialize)]
+struct ChunkHeaderView {
+    chunk_hash: String,
+    height_included: u64,
+}
+
+#[derive(Deserialize)]
+struct ChunkView {
+    #[serde(default)]
+    transactions: Vec<TransactionView>,
+    #[serde(default)]
+    receipts: Vec<ReceiptView>,
+}
+
+#[derive(Deserialize)]
+struct TransactionView {
+    hash: String,
+    signer_id: String,
+    receiver_id: String,
+    #[serde(default)]
+    actions: Vec<Value>,
+}
+
+#[derive(Deserialize)]
+struct ReceiptView {
+    receipt_id: String,
+    receiver_id: String,
+    receipt: Value,
+}
+
+impl ReceiptView {
+    fn calls_watched_method(&self) -> bool {
+        calls_watched_method(
+            self.receipt["Action"]["actions"]
+                .as_array()
+                .into_iter()
+                .flatten(),
+        )
+    }
+}
+
+fn calls_watched_method<'a>(actions: impl IntoIterator<Item = &'a Value>) -> bool {
+    actions
+        .into_iter()
+        .filter_map(|action| action["FunctionCall"]["method_name"].as_str())
+        .any(|method| WATCHED_METHODS.contains(&method))
+}
+
+#[derive(Deserialize)]
+struct LightClientProof {
+    outcome_proof: OutcomeProof,
+}
+
+#[derive(Deserialize)]
+struct OutcomeProof {
+    outcome: OutcomeView,
+}
+
+#[derive(Deserialize)]
+struct OutcomeView {
+    #[serde(default)]
+    logs: Vec<String>,
+    #[serde(default)]
+    receipt_ids: Vec<String>,
+}
+
+struct WatchedReceipt {
+    receipt_id: String,
+    seen_at: u64,
+}
+
+/// A transaction calling the contract. A transaction signed by the contract account itself is
+/// converted into a local receipt that never shows up in a chunk's receipts, so its outcome is
+/// looked up for the receipt id instead.
+struct WatchedTransaction {
+    hash: String,
+    signer_id: String,
+    seen_at: u64,
+}
+
+/// Walks final blocks and reports the fetch contract's events in the order they were found.
+pub struct BlockFollower {
+    http: Client,
+    rpc_url: url::Url,
+    contract_id: AccountId,
+    next_height: Option<u64>,
+    watched: Vec<WatchedReceipt>,
+    watched_transactions: Vec<WatchedTransaction>,
+    /// Receipt ids already watched, with the height they were first seen at.
+    seen_receipts: HashMap<String, u64>,
+}
+
+impl BlockFollower {
+    /// The follower uses its own HTTP client: the RPC node is often local or on a private
+    /// network, which the fetch client's URL policy would refuse.
+    pub fn new(rpc_url: url::Url, contract_id: AccountId) -> Self {
+        Self {
+            http: Client::new(),
+            rpc_url,
+            contract_id,
+            next_height: None,
+            watched: Vec::new(),
+            watched_transactions: Vec::new(),
+            seen_receipts: HashMap::new(),
+        }
+    }
+
+    /// Process the blocks finalized since the previous call and return the events found in
+    /// them. The first call only records the current head.
+    pub async fn poll(&mut self) -> Result<Vec<FetchEvent>> {
+        let head: BlockView = self
+            .call("block", json!({ "finality": "final" }))
+            .await?
+            .context("final block is not available")?;
+        let head_height = head.header.height;
+
+        let Some(start) = self.next_height else {
+            debug!(
+                height = head_height,
+                "Following blocks from the current head"
+            );
+            self.next_height = Some(head_height + 1);
+            return Ok(Vec::new());
+        };
+
+        let end = head_height.min(start + MAX_BLOCKS_PER_POLL - 1);
+        for height in start..=end {
+            self.scan_block(height).await?;
+            self.next_height = Some(height + 1);
+        }
+        if end < head_height {
+            warn!(
+                behind = head_height - end,
+                "Block follower is lagging behind the chain head"
+            );
+        }
+
+        self.collect_events(&head.header.hash, head_height).await
+    }
+
+    async fn scan_block(&mut self, height: u64) -> Result<()> {
+        // Heights can be skipped; a missing block is not an error.
+        let Some(block): Option<BlockView> =
+            self.call("block", json!({ "block_id": height })).await?
+        else {
+            trace!(height, "No block at height");
+            return Ok(());
+        };
+
+        for chunk in block.chunks {
+            if chunk.height_included != block.header.height {
+                continue;
+            }
+            let Some(chunk): Option<ChunkView> = self
+                .call("chunk", json!({ "chunk_id": chunk.chunk_hash }))
+                .await?
+            else {
+                continue;
+            };
+
+            for transaction in chunk.transactions {
+                if transaction.receiver_id == self.contract_id.as_str()
+                    && calls_watched_method(&transaction.actions)
+                {
+                    trace!(tx_hash = %transaction.hash, height, "Watching transaction");
+                    self.watched_transactions.push(WatchedTransaction {
+                        hash: transaction.hash,
+                        signer_id: transaction.signer_id,
+                        seen_at: height,
+                    });
+                }
+            }
+
+            for receipt in chunk.receipts {
+                if receipt.receiver_id == self.contract_id.as_str()
+                    && receipt.calls_watched_method()
+                {
+                    self.watch_receipt(receipt.receipt_id, height);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn watch_receipt(&mut self, receipt_id: String, seen_at: u64) {
+        if self.seen_receipts.contains_key(&receipt_id) {
+            return;
+        }
+        trace!(receipt_id = %receipt_id, seen_at, "Watching receipt");
+        self.seen_receipts.insert(receipt_id.clone(), seen_at);
+        self.watched.push(WatchedReceipt {
+            receipt_id,
+            seen_at,
+        });
+    }
+
+    /// Look up the outcome of every watched transaction and watch the receipt each one was
+    /// converted into.
+    async fn resolve_transactions(&mut self, head_hash: &str, head_height: u64) {
+        for transaction in std::mem::take(&mut self.watched_transactions) {
+            let proof: Result<Option<LightClientProof>> = self
+                .call(
+                    "EXPERIMENTAL_light_client_proof",
+                    json!({
+                        "type": "transaction",
+                        "transaction_hash": transaction.hash,
+                        "sender_id": transaction.signer_id,
+                        "light_client_head": head_hash,
+                    }),
+                )
+                .await;
+
+            match proof {
+                Ok(Some(proof)) => {
+                    for receipt_id in proof.outcome_proof.outcome.receipt_ids {
+                        self.watch_receipt(receipt_id, transaction.seen_at);
+                    }
+                }
+                Ok(None) | Err(_)
+                    if head_height.saturating_sub(transaction.seen_at) < RECEIPT_LOOKUP_BLOCKS =>
+                {
+                    trace!(tx_hash = %transaction.hash, "Transaction outcome not available yet");
+                    self.watched_transactions.push(transaction);
+                }
+                Ok(None) | Err(_) => {
+                    warn!(
+                        tx_hash = %transaction.hash,
+                        "Gave up looking up transaction outcome, leaving it to reconciliation"
+                    );
+                }
+            }
+        }
+    }
+
+    /// Look up the execution outcome of every watched transaction and receipt. Outcomes only
+    /// become provable once a later block is final, so lookups that fail are retried on the next
+    /// poll for a while.
+    async fn collect_events(
+        &mut self,
+        head_hash: &str,
+        head_height: u64,
+    ) -> Result<Vec<FetchEvent>> {
+        self.resolve_transactions(head_hash, head_height).await;
+        self.seen_receipts
+            .retain(|_, seen_at| head_height.saturating_sub(*seen_at) < SEEN_RECEIPT_BLOCKS);
+
+        let mut events = Vec::new();
+        let mut still_watched = Vec::new();
+
+        for receipt in std::mem::take(&mut self.watched) {
+            let proof: Result<Option<LightClientProof>> = self
+                .call(
+                    "EXPERIMENTAL_light_client_proof",
+                    json!({
+                        "type": "receipt",
+                        "receipt_id": receipt.receipt_id,
+                        "receiver_id": self.contract_id,
+                        "light_client_head": head_hash,
+                    }),
+                )
+                .await;
+
+            match proof {
+                Ok(Some(proof)) => events.extend(
+                    proof
+                        .outcome_proof
+                        .outcome
+                        .logs
+                        .iter()
+                        .flat_map(|log| parse_event_log(log)),
+                ),
+                Ok(None) | Err(_)
+                    if head_height.saturating_sub(receipt.seen_at) < RECEIPT_LOOKUP_BLOCKS =>
+                {
+                    trace!(receipt_id = %receipt.receipt_id, "Receipt outcome not available yet");
+                    still_watched.push(receipt);
+                }
+                Ok(None) | Err(_) => {
+                    warn!(
+                        receipt_id = %receipt.receipt_id,
+                        "Gave up looking up receipt outcome, leaving it to reconciliation"
+                    );
+                }
+            }
+        }
+
+        self.watched = still_watched;
+        Ok(events)
+    }
+
+    /// Issue a JSON-RPC call. Lookups of blocks, chunks or outcomes the node doesn't have (yet)
+    /// return `None`; every other failure is an error.
+    async fn call<T: DeserializeOwned>(&self, method: &str, params: Value) -> Result<Option<T>> {
+        let response: RpcResponse<T> = self
+            .http
+            .post(self.rpc_url.clone())
+            .json(&json!({
+                "jsonrpc": "2.0",
+                "id": "relayer",
+                "method": method,
+                "params": params,
+            }))
+            .send()
+            .await
+            .with_context(|| format!("calling {method}"))?
+            .error_for_status()?
+            .json()
+            .await
+            .with_context(|| format!("decoding {method} response"))?;
+
+        match (response.result, response.error) {
+            (Some(result), _) => Ok(Some(result)),
+            (None, Some(err)) if is_not_found(&err) => {
+                trace!(method, error = ?err, "RPC lookup found nothing");
+                Ok(None)
+            }
+            (None, Some(err)) => Err(anyhow!("{method} failed: {} {:?}", err.message, err.data)),
+            (None, None) => Err(anyhow!("{method} returned neither result nor error")),
+        }
+    }
+}
+
+fn is_not_found(err: &RpcError) -> bool {
+    err.cause.as_ref().is_some_and(|cause| {
+        matches!(
+            cause.name.as_str(),
+            "UNKNOWN_BLOCK"
+                | "UNKNOWN_CHUNK"
+                | "UNKNOWN_RECEIPT"
+                | "UNAVAILABLE_SHARD"
+                | "NOT_CONFIRMED"
+                | "UNKNOWN_TRANSACTION_OR_RECEIPT"
+                | "UNKNOWN_EPOCH"
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_fetch_events() {
+        let log = r#"EVENT_JSON:{"standard":"http_fetch","version":"1.0.0","event":"fetch_request","data":[{"request_id":7,"url":"https://example.com","method":"Get"}]}"#;
+        assert_eq!(
+            parse_event_log(log),
+            vec![FetchEvent::Requested { request_id: 7 }]
+        );
+
+        let log = r#"EVENT_JSON:{"standard":"http_fetch","version":"1.0.0","event":"fetch_cancelled","data":[{"request_id":7,"caller":"alice.near"}]}"#;
+        assert_eq!(
+            parse_event_log(log),
+            vec![FetchEvent::Cancelled { request_id: 7 }]
+        );
+    }
+
+    #[test]
+    fn matches_watched_function_calls() {
+        let fetch = json!({ "FunctionCall": { "method_name": "fetch", "args": "" } });
+        let respond = json!({ "FunctionCall": { "method_name": "respond", "args": "" } });
+        let transfer = json!({ "Transfer": { "deposit": "1" } });
+        assert!(calls_watched_method(&[transfer.clone(), fetch]));
+        assert!(!calls_watched_method(&[transfer, respond]));
+    }
+
+    #[test]
+    fn ignores_unrelated_logs() {
+        assert!(parse_event_log("Fetch completed").is_empty());
+        assert!(parse_event_log(
+            r#"EVENT_JSON:{"standard":"nep141","version":"1.0.0","event":"ft_transfer","data":[]}"#
+        )
+        .is_empty());
+        assert!(parse_event_log(
+            r#"EVENT_JSON:{"standard":"http_fetch","version":"1.0.0","event":"relayer_added","data":[{"account_id":"relayer.near"}]}"#
+        )
+        .is_empty());
+    }
+}
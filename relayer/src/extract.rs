@@ -0,0 +1,85 @@
+//! Relayer-side extraction of the JSON fields a request selects with `FetchOptions::extract`, so
+//! only the values the caller needs are uploaded and stored on chain.
+
+use borsh::BorshSerialize;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+/// Mirrors the contract's `ExtractionOutcome`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, BorshSerialize)]
+pub enum ExtractionOutcome {
+    Extracted { missing: Vec<String> },
+    Failed { message: String },
+}
+
+/// Apply the JSON Pointers to `body`, returning the body to upload and what happened. The
+/// result is a JSON object mapping each pointer to the value it selects, `null` for pointers
+/// that select nothing. Bodies that aren't JSON are returned unchanged.
+pub fn extract(body: Vec<u8>, pointers: &[String]) -> (Vec<u8>, ExtractionOutcome) {
+    let document: Value = match serde_json::from_slice(&body) {
+        Ok(document) => document,
+        Err(err) => {
+            let message = format!("response is not JSON: {err}");
+            return (body, ExtractionOutcome::Failed { message });
+        }
+    };
+
+    let mut fields = Map::new();
+    let mut missing = Vec::new();
+    for pointer in pointers {
+        let value = match document.pointer(pointer) {
+            Some(value) => value.clone(),
+            None => {
+                missing.push(pointer.clone());
+                Value::Null
+            }
+        };
+        fields.insert(pointer.clone(), value);
+    }
+
+    let extracted = serde_json::to_vec(&Value::Object(fields)).expect("JSON values serialize");
+    (extracted, ExtractionOutcome::Extracted { missing })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pointers(list: &[&str]) -> Vec<String> {
+        list.iter().map(|pointer| pointer.to_string()).collect()
+    }
+
+    #[test]
+    fn extracts_selected_fields() {
+        let body = br#"{"list":[{"name":"Barcelona","main":{"temp":21.5,"humidity":40},"weather":[{"description":"clear sky"}]}],"count":1}"#;
+        let (extracted, outcome) = extract(
+            body.to_vec(),
+            &pointers(&["/list/0/name", "/list/0/main/temp", "/list/0/sys/country"]),
+        );
+
+        let fields: Value = serde_json::from_slice(&extracted).unwrap();
+        assert_eq!(fields["/list/0/name"], "Barcelona");
+        assert_eq!(fields["/list/0/main/temp"], 21.5);
+        assert!(fields["/list/0/sys/country"].is_null());
+        assert_eq!(fields.as_object().unwrap().len(), 3);
+        assert_eq!(
+            outcome,
+            ExtractionOutcome::Extracted {
+                missing: pointers(&["/list/0/sys/country"])
+            }
+        );
+    }
+
+    #[test]
+    fn empty_pointer_selects_the_whole_document() {
+        let (extracted, _) = extract(br#"{"a": [1, 2]}"#.to_vec(), &pointers(&["", "/a/1"]));
+        assert_eq!(extracted, br#"{"":{"a":[1,2]},"/a/1":2}"#.to_vec());
+    }
+
+    #[test]
+    fn non_json_bodies_are_returned_unchanged() {
+        let (body, outcome) = extract(b"<html>".to_vec(), &pointers(&["/a"]));
+        assert_eq!(body, b"<html>".to_vec());
+        assert!(matches!(outcome, ExtractionOutcome::Failed { .. }));
+    }
+}
@@ -0,0 +1,79 @@
+//! Classifies failed transactions by the structured error the chain reported for them, in the
+//! shape the RPC serializes it: `{"ActionError": {"kind": {"FunctionCallError": ...}}}`.
+
+use serde::Serialize;
+use serde_json::Value;
+
+/// Prefix the runtime puts in front of the message a contract panicked with.
+const PANIC_PREFIX: &str = "Smart contract panicked: ";
+
+/// The `FunctionCallError` an action of the failed transaction ended with.
+fn function_call_error(failure: &impl Serialize) -> Option<Value> {
+    let mut failure = serde_json::to_value(failure).ok()?;
+    failure
+        .get_mut("ActionError")?
+        .get_mut("kind")?
+        .get_mut("FunctionCallError")
+        .map(Value::take)
+}
+
+/// The message a contract call panicked with (`require!`, `env::panic_str`), or `None` if the
+/// transaction failed for another reason.
+pub fn contract_panic(failure: &impl Serialize) -> Option<String> {
+    let error = function_call_error(failure)?;
+    let message = error.get("ExecutionError")?.as_str()?;
+    Some(
+        message
+            .strip_prefix(PANIC_PREFIX)
+            .unwrap_or(message)
+            .to_string(),
+    )
+}
+
+/// Whether a function call failed with the host error `kind`, e.g. `GasExceeded`.
+pub fn is_host_error(failure: &impl Serialize, kind: &str) -> bool {
+    function_call_error(failure)
+        .and_then(|error| error.get("HostError").cloned())
+        .is_some_and(|error| error.as_str() == Some(kind) || error.get(kind).is_some())
+}
+
+/// A failed transaction whose function call ended with `error`, shaped as the RPC reports it.
+#[cfg(test)]
+pub(crate) fn function_call_failure(error: Value) -> Value {
+    serde_json::json!({ "ActionError": { "index": 0, "kind": { "FunctionCallError": error } } })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn reads_contract_panic_messages() {
+        let failure = function_call_failure(json!({
+            "ExecutionError": "Smart contract panicked: Unknown request id"
+        }));
+        assert_eq!(
+            contract_panic(&failure).as_deref(),
+            Some("Unknown request id")
+        );
+
+        let failure = function_call_failure(json!({ "HostError": "GasExceeded" }));
+        assert_eq!(contract_panic(&failure), None);
+        let failure = json!({ "InvalidTxError": "Expired" });
+        assert_eq!(contract_panic(&failure), None);
+    }
+
+    #[test]
+    fn detects_host_errors() {
+        let failure = function_call_failure(json!({ "HostError": "GasExceeded" }));
+        assert!(is_host_error(&failure, "GasExceeded"));
+        assert!(!is_host_error(&failure, "GasLimitExceeded"));
+
+        // A panic message mentioning the error is not the error itself.
+        let failure = function_call_failure(json!({
+            "ExecutionError": "Smart contract panicked: GasExceeded"
+        }));
+        assert!(!is_host_error(&failure, "GasExceeded"));
+    }
+}
@@ -0,0 +1,101 @@
+//! Gas attached to the relayer's contract calls, derived from how many bytes they move. Calls
+//! that still run out are resubmitted with more gas, see [`escalate`].
+//!
+//! The per-byte costs follow the protocol's fees: args are charged when the action is sent and
+//! again when it is read, a chunk is charged per byte it writes, and `respond` reads and hashes
+//! the whole body. Each also covers the contract's copies of the
+//! bytes. `tests/gas.rs` benchmarks the model against the sandbox.
+
+use near_api::types::NearGas;
+use serde::Serialize;
+
+use crate::failure;
+
+/// Prepaid gas limit of a single transaction, shared by the actions of a batch transaction.
+pub const MAX_TRANSACTION_GAS: NearGas = NearGas::from_tgas(300);
+
+const CHUNK_BASE_GAS: u64 = 5_000_000_000_000;
+const RESPOND_BASE_GAS: u64 = 15_000_000_000_000;
+/// Per byte of function call args.
+const ARG_BYTE_GAS: u64 = 15_000_000;
+/// Per byte of the body written to storage.
+const WRITTEN_BYTE_GAS: u64 = 40_000_000;
+/// Per byte of stored body `respond` reads and hashes.
+const VERIFIED_BYTE_GAS: u64 = 15_000_000;
+
+/// Gas for `store_response_chunk_borsh` writing a chunk of `len` bytes. The contract stores each
+/// chunk under its own key, so where the chunk lands in the body doesn't matter.
+pub fn chunk_gas(len: u64) -> NearGas {
+    with_headroom(CHUNK_BASE_GAS + len * (ARG_BYTE_GAS + WRITTEN_BYTE_GAS))
+}
+
+/// Gas for `respond_borsh` verifying a `body_len` byte body, `inline_len` of which is passed in
+/// the call itself.
+pub fn respond_gas(body_len: u64, inline_len: u64) -> NearGas {
+    with_headroom(
+        RESPOND_BASE_GAS
+            + body_len * VERIFIED_BYTE_GAS
+            + inline_len * (ARG_BYTE_GAS + WRITTEN_BYTE_GAS),
+    )
+}
+
+/// Half again on top of the model, capped at what a transaction can attach.
+fn with_headroom(gas: u64) -> NearGas {
+    NearGas::from_gas(
+        gas.saturating_add(gas / 2)
+            .min(MAX_TRANSACTION_GAS.as_gas()),
+    )
+}
+
+/// Gas for another attempt after a call ran out with `gas`: twice as much, up to `limit`. `None`
+/// once `limit` is reached.
+pub fn escalate(gas: NearGas, limit: NearGas) -> Option<NearGas> {
+    (gas.as_gas() < limit.as_gas())
+        .then(|| NearGas::from_gas(gas.as_gas().saturating_mul(2).min(limit.as_gas())))
+}
+
+/// Whether a transaction failed because a function call used up its prepaid gas.
+pub fn is_gas_exceeded(failure: &impl Serialize) -> bool {
+    failure::is_host_error(failure, "GasExceeded")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::failure::function_call_failure;
+    use serde_json::json;
+
+    #[test]
+    fn gas_grows_with_the_body() {
+        assert!(chunk_gas(1_000).as_gas() < chunk_gas(100_000).as_gas());
+        assert!(respond_gas(1_000, 0).as_gas() < respond_gas(1_000_000, 0).as_gas());
+        assert!(respond_gas(1_000, 0).as_gas() < respond_gas(1_000, 1_000).as_gas());
+
+        // A full 1 MB body still fits a batch transaction with its respond action.
+        let batch = chunk_gas(1_000_000).as_gas() + respond_gas(1_000_000, 0).as_gas();
+        assert!(batch <= MAX_TRANSACTION_GAS.as_gas());
+        assert_eq!(chunk_gas(10_000_000), MAX_TRANSACTION_GAS);
+    }
+
+    #[test]
+    fn escalation_doubles_up_to_the_limit() {
+        let limit = NearGas::from_tgas(100);
+        assert_eq!(
+            escalate(NearGas::from_tgas(30), limit),
+            Some(NearGas::from_tgas(60))
+        );
+        assert_eq!(escalate(NearGas::from_tgas(60), limit), Some(limit));
+        assert_eq!(escalate(limit, limit), None);
+    }
+
+    #[test]
+    fn detects_gas_exceeded_failures() {
+        let failure = function_call_failure(json!({ "HostError": "GasExceeded" }));
+        assert!(is_gas_exceeded(&failure));
+        let failure = function_call_failure(json!({
+            "ExecutionError": "Smart contract panicked: GasExceeded"
+        }));
+        assert!(!is_gas_exceeded(&failure));
+        assert!(!is_gas_exceeded(&"GasExceeded"));
+    }
+}
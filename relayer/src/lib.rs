@@ -1,34 +1,411 @@
-use std::{env, str::FromStr, sync::Arc, time::{Duration, Instant}};
+use std::{
+    collections::HashSet,
+    env,
+    io::Write,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+    time::{Duration, Instant},
+};
 
 use anyhow::{anyhow, Context, Result};
+use borsh::BorshSerialize;
+use flate2::{write::GzEncoder, Compression};
 use near_api::types::{
-    transaction::actions::{Action, FunctionCallAction},
-    AccountId, Data, NearGas, TxExecutionStatus,
+    transaction::actions::{
+        AccessKey, AccessKeyPermission, Action, AddKeyAction, FunctionCallAction,
+        FunctionCallPermission,
+    },
+    AccountId, Data, NearGas, NearToken, SecretKey, TxExecutionStatus,
 };
 use near_api::{
     signer::Signer as InnerSigner,
     Contract, NetworkConfig, RPCEndpoint, Signer, Transaction,
 };
-use reqwest::Client;
-use serde::Deserialize;
+use reqwest::{redirect, Client, ClientBuilder, Method};
+use serde::{Deserialize, Serialize};
 use serde_json::json;
+use sha2::{Digest, Sha256};
+use tokio::sync::Semaphore;
 use tokio::time::sleep;
-use tracing::{debug, error, info, trace};
+use tracing::{debug, error, info, trace, warn};
+
+pub mod events;
+pub mod extract;
+pub mod failure;
+pub mod gas;
+pub mod policy;
+pub mod retry;
+pub mod secrets;
+
+use events::{BlockFollower, FetchEvent};
+use extract::{extract, ExtractionOutcome};
+use policy::{is_policy_error, PublicResolver, UrlPolicy};
+use retry::{parse_retry_after, retry, RetryPolicy};
+use secrets::{Rendered, SecretStore};
+
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+enum HttpMethod {
+    #[default]
+    Get,
+    Post,
+    Put,
+    Patch,
+    Delete,
+    Head,
+}
+
+impl HttpMethod {
+    fn as_reqwest(self) -> Method {
+        match self {
+            Self::Get => Method::GET,
+            Self::Post => Method::POST,
+            Self::Put => Method::PUT,
+            Self::Patch => Method::PATCH,
+            Self::Delete => Method::DELETE,
+            Self::Head => Method::HEAD,
+        }
+    }
+
+    fn is_idempotent(self) -> bool {
+        !matches!(self, Self::Post | Self::Patch)
+    }
+}
+
+/// Encoding of an uploaded body, mirroring the contract's `ResponseEncoding`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize, BorshSerialize)]
+enum ResponseEncoding {
+    #[default]
+    Identity,
+    Gzip,
+}
+
+#[derive(Clone, Deserialize, Serialize, BorshSerialize)]
+struct HttpHeader {
+    name: String,
+    value: String,
+}
+
+/// One page of `list_requests`.
+#[derive(Deserialize)]
+struct RequestPage {
+    requests: Vec<PendingRequest>,
+    /// Set when the page stopped at `limit`; the next page starts right after it.
+    last_scanned_id: Option<u64>,
+}
 
 #[derive(Clone, Deserialize)]
 struct PendingRequest {
     request_id: u64,
     url: String,
     #[serde(default)]
-    #[allow(dead_code)]
+    method: HttpMethod,
+    #[serde(default)]
+    headers: Vec<HttpHeader>,
+    #[serde(default)]
+    body: Option<Vec<u8>>,
+    #[serde(default)]
     caller: String,
     #[serde(default)]
     #[allow(dead_code)]
     context: Option<Vec<u8>>,
     yield_id: Vec<u8>,
+    #[serde(default)]
+    quorum: Option<u8>,
+    /// JSON Pointers to extract from the response before uploading it.
+    #[serde(default)]
+    extract: Option<Vec<String>>,
+    /// Per-request cap on the upstream body, on top of `Config::max_response_bytes`.
+    #[serde(default)]
+    max_response_bytes: Option<u64>,
+    /// Encoding the body may be delivered in. The contract doesn't decompress, so the body is
+    /// uploaded compressed exactly when it may be delivered that way.
+    #[serde(default)]
+    response_encoding: Option<ResponseEncoding>,
+    #[serde(default)]
+    voters: Vec<String>,
+    /// Largest body the request's deposit pays storage for.
+    #[serde(default)]
+    storable_response_bytes: Option<u64>,
+    #[serde(default)]
+    deadline_height: Option<u64>,
+    /// Block height of the view call that returned this request.
+    #[serde(skip)]
+    observed_height: u64,
+}
+
+/// Blocks a response needs to land on chain; requests closer than this to their deadline are
+/// skipped, since the contract would reject the response anyway.
+const MIN_BLOCKS_TO_RESPOND: u64 = 5;
+
+impl PendingRequest {
+    /// Blocks left until the request's deadline, as of `observed_height`.
+    fn blocks_left(&self) -> Option<u64> {
+        self.deadline_height
+            .map(|deadline| deadline.saturating_sub(self.observed_height))
+    }
+
+    fn too_close_to_deadline(&self) -> bool {
+        self.blocks_left()
+            .is_some_and(|left| left < MIN_BLOCKS_TO_RESPOND)
+    }
+}
+
+/// Response headers forwarded to the contract alongside the status code.
+const FORWARDED_RESPONSE_HEADERS: &[&str] = &[
+    "content-type",
+    "content-language",
+    "etag",
+    "last-modified",
+    "date",
+    "cache-control",
+    "expires",
+    "age",
+    "location",
+    "retry-after",
+];
+
+/// Forwarded headers that change from one fetch to the next. Quorum relayers must report the
+/// same headers to agree, so these are left out of quorum responses.
+const VOLATILE_RESPONSE_HEADERS: &[&str] = &["date", "age", "expires", "retry-after"];
+
+/// Mirrors the contract's `FetchErrorKind`, reported through `respond_error`.
+#[derive(Clone, Copy, Debug, Serialize)]
+enum FetchErrorKind {
+    InvalidRequest,
+    Connection,
+    Timeout,
+    Redirect,
+    Body,
+    Other,
+    PolicyRejected,
+    ResponseTooLarge,
+    InsufficientDeposit,
+}
+
+impl FetchErrorKind {
+    fn from_reqwest(err: &reqwest::Error) -> Self {
+        if is_policy_error(err) {
+            Self::PolicyRejected
+        } else if err.is_builder() {
+            Self::InvalidRequest
+        } else if err.is_timeout() {
+            Self::Timeout
+        } else if err.is_connect() {
+            Self::Connection
+        } else if err.is_redirect() {
+            Self::Redirect
+        } else if err.is_body() || err.is_decode() {
+            Self::Body
+        } else {
+            Self::Other
+        }
+    }
+}
+
+/// Must stay within the contract's `MAX_ERROR_MESSAGE_LEN`.
+const MAX_ERROR_MESSAGE_LEN: usize = 512;
+
+/// Render an error with its source chain.
+fn describe_error(err: &(dyn std::error::Error + 'static)) -> String {
+    let mut message = err.to_string();
+    let mut source = err.source();
+    while let Some(cause) = source {
+        message.push_str(": ");
+        message.push_str(&cause.to_string());
+        source = cause.source();
+    }
+    message
+}
+
+/// Truncate `message` on a char boundary to fit on chain. Runs after redaction, so a secret
+/// cut in half by the limit can't slip past it.
+fn truncate_error_message(mut message: String) -> String {
+    if message.len() > MAX_ERROR_MESSAGE_LEN {
+        let mut end = MAX_ERROR_MESSAGE_LEN;
+        while !message.is_char_boundary(end) {
+            end -= 1;
+        }
+        message.truncate(end);
+    }
+    message
+}
+
+/// Contract rejections meaning the request was cancelled, expired or resolved by another relayer
+/// while we were still working on it.
+fn is_stale_request_failure(failure: &impl Serialize) -> bool {
+    failure::contract_panic(failure).is_some_and(|message| {
+        matches!(
+            message.as_str(),
+            "Request already resolved" | "Unknown request id" | "Request deadline has passed"
+        )
+    })
+}
+
+/// Contract rejections meaning the request's deposit can't pay for storing the body. Resending
+/// can't help, so the request is failed with `InsufficientDeposit` instead.
+fn is_insufficient_deposit_failure(failure: &impl Serialize) -> bool {
+    failure::contract_panic(failure)
+        .is_some_and(|message| message.starts_with("Attached deposit does not cover storage"))
+}
+
+/// Fail a request whose body the contract refused to store for lack of deposit.
+async fn fail_insufficient_deposit(
+    config: &Config,
+    request_id: u64,
+    yield_id: Vec<u8>,
+) -> Result<()> {
+    warn!(
+        request_id,
+        "Deposit does not cover storing the response, reporting failure to contract"
+    );
+    let message = "the request's deposit does not cover storing the response body".to_string();
+    send_error(
+        config,
+        request_id,
+        yield_id,
+        FetchErrorKind::InsufficientDeposit,
+        message,
+    )
+    .await
+}
+
+/// Status line metadata reported back through `respond`.
+struct ResponseMeta {
+    status_code: u16,
+    headers: Vec<HttpHeader>,
+    /// sha256 and length of the uploaded body, filled in once the body has been read.
+    body_hash: Vec<u8>,
+    body_len: u64,
+    /// Set when the request asked for extraction.
+    extraction: Option<ExtractionOutcome>,
+    body_encoding: ResponseEncoding,
+}
+
+impl ResponseMeta {
+    fn from_response(response: &reqwest::Response) -> Self {
+        let headers = FORWARDED_RESPONSE_HEADERS
+            .iter()
+            .filter_map(|name| {
+                let value = response.headers().get(*name)?.to_str().ok()?;
+                Some(HttpHeader {
+                    name: (*name).to_string(),
+                    value: value.to_string(),
+                })
+            })
+            .collect();
+
+        Self {
+            status_code: response.status().as_u16(),
+            headers,
+            body_hash: Vec::new(),
+            body_len: 0,
+            extraction: None,
+            body_encoding: ResponseEncoding::Identity,
+        }
+    }
+}
+
+/// Page size used when paging through `list_requests`.
+const LIST_PAGE_SIZE: u32 = 50;
+
+/// Bytes uploaded per `store_response_chunk_borsh` call. The contract stores every chunk under
+/// its own key, so per [`gas::chunk_gas`] all chunks cost the same; half the contract's body cap
+/// uploads a full body in two calls, and a body of a single chunk fits a batch transaction
+/// together with its `respond` action.
+const CHUNK_SIZE: usize = 500_000;
+
+/// How often the event-driven relayer asks the RPC node for new final blocks.
+const BLOCK_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+const DEFAULT_RECONCILE_INTERVAL: Duration = Duration::from_secs(30);
+
+const DEFAULT_MAX_CONCURRENCY: usize = 4;
+
+/// Largest upstream body the relayer reads by default. Above the contract's 1 MB storage cap
+/// so that extraction can still shrink larger JSON documents.
+const DEFAULT_MAX_RESPONSE_BYTES: u64 = 5_000_000;
+
+/// Largest body the contract stores, its `max_response_bytes()`.
+const CONTRACT_MAX_RESPONSE_BYTES: u64 = 1_000_000;
+
+/// Contract methods the relayer calls, and so the only ones provisioned access keys may call.
+const RELAYER_METHODS: &[&str] = &[
+    "respond",
+    "respond_error",
+    "store_response_chunk",
+    "store_response_chunk_base64",
+    "respond_borsh",
+    "store_response_chunk_borsh",
+];
+
+/// Request ids currently being handled, shared by every clone of a [`Config`] so a poll that
+/// overlaps with running work doesn't dispatch the same request twice.
+#[derive(Clone, Default)]
+struct InFlight(Arc<Mutex<HashSet<u64>>>);
+
+impl InFlight {
+    /// Mark `request_id` as in flight until the returned guard is dropped. Returns `None` if it
+    /// already is.
+    fn claim(&self, request_id: u64) -> Option<InFlightGuard> {
+        let mut ids = self.0.lock().expect("in-flight set poisoned");
+        ids.insert(request_id).then(|| InFlightGuard {
+            in_flight: self.clone(),
+            request_id,
+        })
+    }
+
+    fn contains(&self, request_id: u64) -> bool {
+        self.0
+            .lock()
+            .expect("in-flight set poisoned")
+            .contains(&request_id)
+    }
+
+    fn len(&self) -> usize {
+        self.0.lock().expect("in-flight set poisoned").len()
+    }
 }
 
-const CHUNK_SIZE: usize = 300_000; // 300 KB - tested to use ~207 TGas in batch transactions (300 TGas limit)
+struct InFlightGuard {
+    in_flight: InFlight,
+    request_id: u64,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        if let Ok(mut ids) = self.in_flight.0.lock() {
+            ids.remove(&self.request_id);
+        }
+    }
+}
+
+/// How the relayer discovers new requests.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Mode {
+    /// Page through `list_requests` every `poll_interval`.
+    #[default]
+    Poll,
+    /// Follow final blocks for `fetch_request` events, reconciling with a full
+    /// `list_requests` scan every `reconcile_interval`.
+    Events,
+}
+
+impl FromStr for Mode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "poll" => Ok(Mode::Poll),
+            "events" => Ok(Mode::Events),
+            other => Err(anyhow!(
+                "unknown relayer mode {other:?}, expected poll or events"
+            )),
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct Config {
@@ -37,6 +414,27 @@ pub struct Config {
     pub relayer_id: AccountId,
     pub signer: Arc<Signer>,
     pub poll_interval: Duration,
+    pub mode: Mode,
+    pub reconcile_interval: Duration,
+    pub max_concurrency: usize,
+    /// Upstream bodies beyond this many bytes are abandoned and reported as `ResponseTooLarge`.
+    pub max_response_bytes: u64,
+    /// Which URLs may be fetched; enforced before the request, on DNS results and on redirects.
+    pub url_policy: Arc<UrlPolicy>,
+    /// Secrets substituted for `{{secret:name}}` placeholders in requests.
+    pub secrets: Arc<SecretStore>,
+    /// Retries for the upstream HTTP request.
+    pub fetch_retry: RetryPolicy,
+    /// Retries for submitting transactions when the RPC call itself fails.
+    pub tx_retry: RetryPolicy,
+    /// Function-call access keys of `relayer_id` used in turn for contract calls, so concurrent
+    /// transactions don't race for the nonce of a single key.
+    access_keys: Vec<Arc<Signer>>,
+    next_access_key: Arc<AtomicUsize>,
+    workers: Arc<Semaphore>,
+    in_flight: InFlight,
+    /// Client for requests that carry secrets, built on first use.
+    secret_http: Arc<OnceLock<Client>>,
 }
 
 impl Config {
@@ -53,7 +451,98 @@ impl Config {
             relayer_id,
             signer,
             poll_interval,
+            mode: Mode::default(),
+            reconcile_interval: DEFAULT_RECONCILE_INTERVAL,
+            max_concurrency: DEFAULT_MAX_CONCURRENCY,
+            max_response_bytes: DEFAULT_MAX_RESPONSE_BYTES,
+            url_policy: Arc::new(UrlPolicy::default()),
+            secrets: Arc::new(SecretStore::default()),
+            fetch_retry: RetryPolicy::fetch_default(),
+            tx_retry: RetryPolicy::transaction_default(),
+            access_keys: Vec::new(),
+            next_access_key: Arc::new(AtomicUsize::new(0)),
+            workers: Arc::new(Semaphore::new(DEFAULT_MAX_CONCURRENCY)),
+            in_flight: InFlight::default(),
+            secret_http: Arc::new(OnceLock::new()),
+        }
+    }
+
+    /// Limit how many requests are handled at the same time.
+    pub fn with_max_concurrency(mut self, max_concurrency: usize) -> Self {
+        self.max_concurrency = max_concurrency.max(1);
+        self.workers = Arc::new(Semaphore::new(self.max_concurrency));
+        self
+    }
+
+    pub fn with_max_response_bytes(mut self, max_response_bytes: u64) -> Self {
+        self.max_response_bytes = max_response_bytes;
+        self
+    }
+
+    pub fn with_url_policy(mut self, url_policy: UrlPolicy) -> Self {
+        self.url_policy = Arc::new(url_policy);
+        self
+    }
+
+    pub fn with_secrets(mut self, secrets: SecretStore) -> Self {
+        self.secrets = Arc::new(secrets);
+        self
+    }
+
+    pub fn with_fetch_retry(mut self, policy: RetryPolicy) -> Self {
+        self.fetch_retry = policy;
+        self
+    }
+
+    pub fn with_tx_retry(mut self, policy: RetryPolicy) -> Self {
+        self.tx_retry = policy;
+        self
+    }
+
+    /// Sign single contract calls with `keys` in round-robin order instead of `signer`. The keys
+    /// must be access keys of `relayer_id`, e.g. ones created by [`provision_access_keys`].
+    pub fn with_access_keys(mut self, keys: &[SecretKey]) -> Result<Self> {
+        self.access_keys = keys
+            .iter()
+            .map(|key| Ok(Signer::new(InnerSigner::from_secret_key(key.clone()))?))
+            .collect::<Result<_>>()?;
+        Ok(self)
+    }
+
+    fn has_access_keys(&self) -> bool {
+        !self.access_keys.is_empty()
+    }
+
+    /// The signer for the next single-function-call transaction.
+    fn next_signer(&self) -> Arc<Signer> {
+        if self.access_keys.is_empty() {
+            return self.signer.clone();
         }
+        let index = self.next_access_key.fetch_add(1, Ordering::Relaxed) % self.access_keys.len();
+        self.access_keys[index].clone()
+    }
+
+    /// Number of requests currently being handled.
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.len()
+    }
+
+    pub fn with_mode(mut self, mode: Mode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn with_reconcile_interval(mut self, reconcile_interval: Duration) -> Self {
+        self.reconcile_interval = reconcile_interval.max(Duration::from_secs(1));
+        self
+    }
+
+    fn rpc_url(&self) -> Result<url::Url> {
+        self.network
+            .rpc_endpoints
+            .first()
+            .map(|endpoint| endpoint.url.clone())
+            .context("network config has no RPC endpoint")
     }
 
     pub fn from_parts(
@@ -98,14 +587,128 @@ impl Config {
             .ok()
             .and_then(|v| v.parse().ok());
 
-        Self::from_parts(&rpc_url, &contract_id, &relayer_id, &secret_key, poll)
+        let mode = match env::var("RELAYER_MODE") {
+            Ok(mode) => mode.parse()?,
+            Err(_) => Mode::default(),
+        };
+        let reconcile = env::var("RECONCILE_INTERVAL_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .map_or(DEFAULT_RECONCILE_INTERVAL, Duration::from_secs);
+        let max_concurrency = env::var("MAX_CONCURRENT_REQUESTS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_MAX_CONCURRENCY);
+        let max_response_bytes =
+            env_var("MAX_RESPONSE_BYTES").unwrap_or(DEFAULT_MAX_RESPONSE_BYTES);
+        let mut url_policy = UrlPolicy::default();
+        if let Ok(hosts) = env::var("ALLOWED_HOSTS") {
+            url_policy.allow_hosts = UrlPolicy::parse_hosts(&hosts);
+        }
+        if let Ok(hosts) = env::var("DENIED_HOSTS") {
+            url_policy.deny_hosts = UrlPolicy::parse_hosts(&hosts);
+        }
+        if let Ok(schemes) = env::var("ALLOWED_SCHEMES") {
+            url_policy.allowed_schemes = schemes
+                .split(',')
+                .map(|scheme| scheme.trim().to_string())
+                .filter(|scheme| !scheme.is_empty())
+                .collect();
+        }
+        if let Some(allow_private) = env_var::<bool>("ALLOW_PRIVATE_IPS") {
+            url_policy.block_private_ips = !allow_private;
+        }
+        let secrets = match env::var("RELAYER_SECRETS_FILE") {
+            Ok(path) => SecretStore::load(path.as_ref())?,
+            Err(_) => SecretStore::default(),
+        };
+        let mut fetch_retry = RetryPolicy::fetch_default();
+        if let Some(attempts) = env_var("FETCH_MAX_ATTEMPTS") {
+            fetch_retry = fetch_retry.with_max_attempts(attempts);
+        }
+        if let Some(ms) = env_var("FETCH_RETRY_BASE_MS") {
+            fetch_retry = fetch_retry.with_base_delay(Duration::from_millis(ms));
+        }
+        if let Ok(statuses) = env::var("FETCH_RETRY_STATUSES") {
+            let statuses =
+                RetryPolicy::parse_statuses(&statuses).context("invalid FETCH_RETRY_STATUSES")?;
+            fetch_retry = fetch_retry.with_retry_statuses(statuses);
+        }
+        if let Some(jitter) = env_var("FETCH_RETRY_JITTER") {
+            fetch_retry = fetch_retry.with_jitter(jitter);
+        }
+        let mut tx_retry = RetryPolicy::transaction_default();
+        if let Some(attempts) = env_var("TX_MAX_ATTEMPTS") {
+            tx_retry = tx_retry.with_max_attempts(attempts);
+        }
+        if let Some(ms) = env_var("TX_RETRY_BASE_MS") {
+            tx_retry = tx_retry.with_base_delay(Duration::from_millis(ms));
+        }
+        let access_keys = match env::var("RELAYER_ACCESS_KEYS") {
+            Ok(keys) => keys
+                .split(',')
+                .map(str::trim)
+                .filter(|key| !key.is_empty())
+                .map(|key| key.parse().context("invalid key in RELAYER_ACCESS_KEYS"))
+                .collect::<Result<Vec<SecretKey>>>()?,
+            Err(_) => Vec::new(),
+        };
+
+        info!(
+            mode = ?mode,
+            reconcile_interval_secs = reconcile.as_secs(),
+            max_concurrency,
+            access_keys = access_keys.len(),
+            "Relayer mode selected"
+        );
+
+        Ok(
+            Self::from_parts(&rpc_url, &contract_id, &relayer_id, &secret_key, poll)?
+                .with_mode(mode)
+                .with_reconcile_interval(reconcile)
+                .with_max_concurrency(max_concurrency)
+                .with_max_response_bytes(max_response_bytes)
+                .with_url_policy(url_policy)
+                .with_secrets(secrets)
+                .with_fetch_retry(fetch_retry)
+                .with_tx_retry(tx_retry)
+                .with_access_keys(&access_keys)?,
+        )
     }
 
+    /// Client for upstream fetches, enforcing `url_policy` on DNS results and redirects.
     pub fn http_client(&self) -> Result<Client> {
-        Ok(Client::builder()
-            .user_agent("http-fetch-relayer/0.1.0")
+        Ok(self
+            .client_builder()
+            .redirect(self.url_policy.redirect_policy())
             .build()?)
     }
+
+    /// Client for requests that carry secrets. It doesn't follow redirects, which could hand
+    /// the secrets to a host they're not allowed to reach; the 3xx response is returned as is.
+    fn secret_http_client(&self) -> Result<Client> {
+        if let Some(client) = self.secret_http.get() {
+            return Ok(client.clone());
+        }
+        let client = self
+            .client_builder()
+            .redirect(redirect::Policy::none())
+            .build()?;
+        Ok(self.secret_http.get_or_init(|| client).clone())
+    }
+
+    fn client_builder(&self) -> ClientBuilder {
+        let builder = Client::builder().user_agent("http-fetch-relayer/0.1.0");
+        if self.url_policy.block_private_ips {
+            builder.dns_resolver(Arc::new(PublicResolver))
+        } else {
+            builder
+        }
+    }
+}
+
+fn env_var<T: FromStr>(name: &str) -> Option<T> {
+    env::var(name).ok().and_then(|v| v.parse().ok())
 }
 
 fn build_network_config(rpc_url: url::Url) -> NetworkConfig {
@@ -123,29 +726,136 @@ fn build_network_config(rpc_url: url::Url) -> NetworkConfig {
 
 pub async fn process_once(config: &Config, http: &Client) -> Result<bool> {
     trace!("Fetching pending requests from contract");
-    let pending = fetch_pending_requests(config).await?;
+    let mut pending = fetch_pending_requests(config).await?;
+    pending.retain(|request| {
+        !already_voted(config, request) && !config.in_flight.contains(request.request_id)
+    });
+    pending.retain(|request| {
+        let keep = !request.too_close_to_deadline();
+        if !keep {
+            debug!(
+                request_id = request.request_id,
+                blocks_left = request.blocks_left(),
+                "Request is too close to its deadline, skipping"
+            );
+        }
+        keep
+    });
 
     if pending.is_empty() {
         debug!("No pending requests found");
         return Ok(false);
     }
 
+    // Workers are handed out in dispatch order, so the most urgent requests go first.
+    pending.sort_by_key(|request| request.deadline_height.unwrap_or(u64::MAX));
+
     info!(count = pending.len(), "Found pending requests to process");
 
     for request in pending {
-        info!(
-            request_id = request.request_id,
-            url = %request.url,
-            caller = %request.caller,
-            "Processing request"
-        );
-        handle_request(config, http, request).await?;
+        dispatch(config, http, request);
     }
 
     Ok(true)
 }
 
+/// Hand `request` to a background task, which waits for a free worker slot before handling it.
+/// A failing request is logged and left for the next poll without affecting the others. Returns
+/// `false` if the request is already in flight.
+fn dispatch(config: &Config, http: &Client, request: PendingRequest) -> bool {
+    let request_id = request.request_id;
+    let Some(guard) = config.in_flight.claim(request_id) else {
+        debug!(request_id, "Request is already in flight");
+        return false;
+    };
+
+    info!(
+        request_id,
+        url = %request.url,
+        caller = %request.caller,
+        quorum = ?request.quorum,
+        in_flight = config.in_flight(),
+        "Dispatching request"
+    );
+
+    let config = config.clone();
+    let http = http.clone();
+    tokio::spawn(async move {
+        let _guard = guard;
+        let Ok(_permit) = config.workers.clone().acquire_owned().await else {
+            return;
+        };
+        if let Err(e) = handle_request(&config, &http, request).await {
+            error!(request_id, error = %e, "Error processing request, will retry on a later poll");
+        }
+    });
+
+    true
+}
+
+/// Quorum requests stay pending until enough relayers respond; skip ones we already answered.
+fn already_voted(config: &Config, request: &PendingRequest) -> bool {
+    request
+        .voters
+        .iter()
+        .any(|voter| voter == config.relayer_id.as_str())
+}
+
+/// Dispatch the requests announced by `events`. Each request is re-read with `get_request`, so
+/// ones that were resolved or cancelled in the meantime are skipped. Returns how many requests
+/// were dispatched.
+pub async fn process_events(
+    config: &Config,
+    http: &Client,
+    events: &[FetchEvent],
+) -> Result<usize> {
+    let cancelled: HashSet<u64> = events
+        .iter()
+        .filter(|event| matches!(event, FetchEvent::Cancelled { .. }))
+        .map(FetchEvent::request_id)
+        .collect();
+    let mut dispatched = 0;
+
+    for event in events {
+        let FetchEvent::Requested { request_id } = *event else {
+            continue;
+        };
+        if cancelled.contains(&request_id) {
+            info!(request_id, "Request was cancelled before it was dispatched");
+            continue;
+        }
+        if config.in_flight.contains(request_id) {
+            continue;
+        }
+
+        let Some(request) = fetch_request(config, request_id).await? else {
+            debug!(request_id, "Request is no longer pending");
+            continue;
+        };
+        if already_voted(config, &request) {
+            continue;
+        }
+        if request.too_close_to_deadline() {
+            debug!(request_id, "Request is too close to its deadline, skipping");
+            continue;
+        }
+
+        if dispatch(config, http, request) {
+            dispatched += 1;
+        }
+    }
+
+    Ok(dispatched)
+}
+
 pub async fn run(config: Config) -> Result<()> {
+    match config.mode {
+        Mode::Poll => run_polling(config).await,
+        Mode::Events => run_event_driven(config).await,
+    }
+}
+
+async fn run_polling(config: Config) -> Result<()> {
     info!("Starting relayer main loop");
     let http = config.http_client()?;
 
@@ -169,59 +879,274 @@ pub async fn run(config: Config) -> Result<()> {
     }
 }
 
-async fn fetch_pending_requests(config: &Config) -> Result<Vec<PendingRequest>> {
-    let start = Instant::now();
-    let contract = Contract(config.contract_id.clone());
+async fn run_event_driven(config: Config) -> Result<()> {
+    info!(
+        reconcile_interval_secs = config.reconcile_interval.as_secs(),
+        "Starting event-driven relayer loop"
+    );
+    let http = config.http_client()?;
+    let mut follower = BlockFollower::new(config.rpc_url()?, config.contract_id.clone());
+    let mut last_reconcile: Option<Instant> = None;
+
+    loop {
+        // Catch anything the block follower missed: requests made before startup, blocks skipped
+        // while lagging and receipts whose outcome couldn't be looked up.
+        if last_reconcile.is_none_or(|at| at.elapsed() >= config.reconcile_interval) {
+            debug!("Reconciling with list_requests");
+            if let Err(e) = process_once(&config, &http).await {
+                error!(error = %e, "Error reconciling pending requests");
+            }
+            last_reconcile = Some(Instant::now());
+        }
+
+        match follower.poll().await {
+            Ok(events) if events.is_empty() => {}
+            Ok(events) => {
+                debug!(count = events.len(), "Received contract events");
+                if let Err(e) = process_events(&config, &http, &events).await {
+                    error!(error = %e, "Error processing events");
+                }
+            }
+            Err(e) => {
+                error!(error = %e, "Error following blocks, will retry");
+            }
+        }
 
-    debug!(contract_id = %config.contract_id, "Calling list_requests on contract");
+        sleep(BLOCK_POLL_INTERVAL).await;
+    }
+}
+
+/// Add `count` new function-call access keys to the relayer account, restricted to the fetch
+/// contract's relayer methods, and return their secret keys. The transaction is signed with
+/// `config.signer`, which must be a full access key. `allowance` caps the gas each key may spend;
+/// `None` leaves it unlimited.
+pub async fn provision_access_keys(
+    config: &Config,
+    count: usize,
+    allowance: Option<NearToken>,
+) -> Result<Vec<SecretKey>> {
+    let keys = (0..count)
+        .map(|_| near_api::signer::generate_secret_key().context("generating access key"))
+        .collect::<Result<Vec<SecretKey>>>()?;
+
+    let mut tx = Transaction::construct(config.relayer_id.clone(), config.relayer_id.clone());
+    for key in &keys {
+        tx = tx.add_action(Action::AddKey(Box::new(AddKeyAction {
+            public_key: key.public_key(),
+            access_key: AccessKey {
+                nonce: 0,
+                permission: AccessKeyPermission::FunctionCall(FunctionCallPermission {
+                    allowance,
+                    receiver_id: config.contract_id.to_string(),
+                    method_names: RELAYER_METHODS.iter().map(|m| m.to_string()).collect(),
+                }),
+            },
+        })));
+    }
+
+    info!(
+        count,
+        relayer_id = %config.relayer_id,
+        contract_id = %config.contract_id,
+        "Provisioning function-call access keys"
+    );
+    tx.with_signer(config.signer.clone())
+        .send_to(&config.network)
+        .await?
+        .into_result()
+        .map_err(|failure| anyhow!("adding access keys failed: {:?}", failure))?;
+
+    Ok(keys)
+}
 
-    let response: Data<Vec<PendingRequest>> = contract
-        .call_function("list_requests", ())
-        .context("serializing list_requests args")?
+async fn fetch_request(config: &Config, request_id: u64) -> Result<Option<PendingRequest>> {
+    let response: Data<Option<PendingRequest>> = Contract(config.contract_id.clone())
+        .call_function("get_request", json!({ "request_id": request_id }))
+        .context("serializing get_request args")?
         .read_only()
         .fetch_from(&config.network)
         .await?;
 
+    Ok(response.data.map(|request| PendingRequest {
+        observed_height: response.block_height,
+        ..request
+    }))
+}
+
+async fn fetch_pending_requests(config: &Config) -> Result<Vec<PendingRequest>> {
+    let start = Instant::now();
+    let contract = Contract(config.contract_id.clone());
+    let mut pending: Vec<PendingRequest> = Vec::new();
+    let mut min_request_id: u64 = 0;
+
+    loop {
+        debug!(
+            contract_id = %config.contract_id,
+            min_request_id,
+            limit = LIST_PAGE_SIZE,
+            "Calling list_requests on contract"
+        );
+
+        let response: Data<RequestPage> = contract
+            .call_function(
+                "list_requests",
+                json!({ "min_request_id": min_request_id, "limit": LIST_PAGE_SIZE }),
+            )
+            .context("serializing list_requests args")?
+            .read_only()
+            .fetch_from(&config.network)
+            .await?;
+
+        let observed_height = response.block_height;
+        let page = response.data;
+        pending.extend(page.requests.into_iter().map(|request| PendingRequest {
+            observed_height,
+            ..request
+        }));
+
+        // Pages are keyed by id, so requests resolved in between don't shift the next page.
+        let Some(last_scanned_id) = page.last_scanned_id else {
+            break;
+        };
+        min_request_id = last_scanned_id + 1;
+    }
+
     let elapsed = start.elapsed();
     debug!(
-        count = response.data.len(),
+        count = pending.len(),
         elapsed_ms = elapsed.as_millis(),
         "Fetched pending requests"
     );
 
-    Ok(response.data)
+    Ok(pending)
 }
 
 async fn handle_request(config: &Config, http: &Client, request: PendingRequest) -> Result<()> {
     let request_id = request.request_id;
     let url = &request.url;
-
-    info!(request_id, url = %url, "Starting HTTP fetch");
-    let fetch_start = Instant::now();
-
-    let response = http
-        .get(url)
-        .send()
-        .await
-        .with_context(|| format!("issuing GET to {}", url))?;
-
-    let status = response.status();
-    let fetch_elapsed = fetch_start.elapsed();
+    let method = request.method.as_reqwest();
 
     info!(
         request_id,
         url = %url,
-        status = status.as_u16(),
-        elapsed_ms = fetch_elapsed.as_millis(),
-        "HTTP request completed"
+        method = %method,
+        header_count = request.headers.len(),
+        has_body = request.body.is_some(),
+        "Starting HTTP fetch"
     );
+    let checked = url::Url::parse(url)
+        .map_err(|err| (FetchErrorKind::InvalidRequest, describe_error(&err)))
+        .and_then(|parsed| {
+            config
+                .url_policy
+                .check(&parsed)
+                .map_err(|err| (FetchErrorKind::PolicyRejected, describe_error(&err)))
+        });
+    if let Err((kind, message)) = checked {
+        warn!(
+            request_id,
+            url = %url,
+            kind = ?kind,
+            error = %message,
+            "URL rejected, reporting failure to contract"
+        );
+        return send_error(config, request_id, request.yield_id, kind, message).await;
+    }
 
-    let bytes = response
-        .bytes()
-        .await
-        .context("reading HTTP body")?
-        .to_vec();
+    let headers: Vec<(String, String)> = request
+        .headers
+        .iter()
+        .map(|header| (header.name.clone(), header.value.clone()))
+        .collect();
+    let rendered = config
+        .secrets
+        .render(&request.caller, url, &headers, request.body.as_deref());
+    let rendered = match rendered {
+        Ok(rendered) => rendered,
+        Err(err) => {
+            warn!(
+                request_id,
+                caller = %request.caller,
+                error = %err,
+                "Secrets unavailable, reporting failure to contract"
+            );
+            let (kind, message) = (FetchErrorKind::PolicyRejected, describe_error(&err));
+            return send_error(config, request_id, request.yield_id, kind, message).await;
+        }
+    };
+    let secret_http;
+    let http = if rendered.uses_secrets() {
+        secret_http = config.secret_http_client()?;
+        &secret_http
+    } else {
+        http
+    };
+
+    let (mut meta, bytes) = match fetch_upstream(config, http, &request, &rendered).await {
+        Ok(fetched) => fetched,
+        Err((kind, message)) => {
+            let message = rendered.redact(&message);
+            warn!(
+                request_id,
+                url = %url,
+                method = %method,
+                kind = ?kind,
+                error = %message,
+                "HTTP request failed, reporting failure to contract"
+            );
+            return send_error(config, request_id, request.yield_id, kind, message).await;
+        }
+    };
+    if request.quorum.is_some() {
+        meta.headers
+            .retain(|header| !VOLATILE_RESPONSE_HEADERS.contains(&header.name.as_str()));
+    }
 
+    let bytes = match &request.extract {
+        Some(pointers) => {
+            let received = bytes.len();
+            let (extracted, outcome) = extract(bytes, pointers);
+            match &outcome {
+                ExtractionOutcome::Extracted { missing } => info!(
+                    request_id,
+                    received_bytes = received,
+                    extracted_bytes = extracted.len(),
+                    missing = missing.len(),
+                    "Extracted requested fields from response"
+                ),
+                ExtractionOutcome::Failed { message } => warn!(
+                    request_id,
+                    error = %message,
+                    "Could not extract fields, uploading the full response"
+                ),
+            }
+            meta.extraction = Some(outcome);
+            extracted
+        }
+        None => bytes,
+    };
+    // Quorum relayers must upload identical bytes, which compressor output doesn't guarantee.
+    let compress =
+        request.response_encoding == Some(ResponseEncoding::Gzip) && request.quorum.is_none();
+    let bytes = if compress {
+        let compressed = gzip(&bytes)?;
+        if compressed.len() < bytes.len() {
+            info!(
+                request_id,
+                original_bytes = bytes.len(),
+                compressed_bytes = compressed.len(),
+                "Uploading gzip-compressed body"
+            );
+            meta.body_encoding = ResponseEncoding::Gzip;
+            compressed
+        } else {
+            bytes
+        }
+    } else {
+        bytes
+    };
+    meta.body_hash = Sha256::digest(&bytes).to_vec();
+    meta.body_len = bytes.len() as u64;
     let body_size = bytes.len();
     info!(
         request_id,
@@ -229,17 +1154,69 @@ async fn handle_request(config: &Config, http: &Client, request: PendingRequest)
         "HTTP response body received"
     );
 
+    // Bodies are read up to the relayer's own limit, so after extraction and encoding the upload
+    // can still be larger than the contract stores.
+    let upload_limit = request
+        .max_response_bytes
+        .map_or(CONTRACT_MAX_RESPONSE_BYTES, |limit| {
+            limit.min(CONTRACT_MAX_RESPONSE_BYTES)
+        });
+    if meta.body_len > upload_limit {
+        warn!(
+            request_id,
+            body_size_bytes = body_size,
+            upload_limit_bytes = upload_limit,
+            "Response body is too large to store, reporting failure to contract"
+        );
+        let message = format!("response body of {body_size} bytes exceeds {upload_limit} bytes");
+        let kind = FetchErrorKind::ResponseTooLarge;
+        return send_error(config, request_id, request.yield_id, kind, message).await;
+    }
+
+    if let Some(storable) = request.storable_response_bytes {
+        // A partial upload of ours from an earlier attempt gets replaced, so its storage is
+        // available too.
+        let storable = if meta.body_len > storable {
+            let stored = fetch_response_progress(config, request_id).await?;
+            storable + stored.map_or(0, |progress| progress.length)
+        } else {
+            storable
+        };
+        if meta.body_len > storable {
+            warn!(
+                request_id,
+                body_size_bytes = body_size,
+                storable_bytes = storable,
+                "Deposit does not cover storing the response, reporting failure to contract"
+            );
+            let message = format!(
+                "response body of {body_size} bytes needs more storage than the request's deposit pays for ({storable} bytes)"
+            );
+            let kind = FetchErrorKind::InsufficientDeposit;
+            return send_error(config, request_id, request.yield_id, kind, message).await;
+        }
+    }
+
     if bytes.is_empty() {
         debug!(request_id, "Response body is empty, sending inline");
-        send_response(config, request.request_id, request.yield_id, Some(bytes)).await
-    } else if bytes.len() <= CHUNK_SIZE {
-        // Single chunk - use batch transaction
+        send_response(
+            config,
+            request.request_id,
+            request.yield_id,
+            Some(bytes),
+            &meta,
+        )
+        .await
+    } else if bytes.len() <= CHUNK_SIZE && !config.has_access_keys() {
+        // Single chunk - use batch transaction. Function-call access keys can only sign
+        // single-action transactions, so with a key pool the chunked path below is used instead.
         info!(
             request_id,
             body_size_bytes = body_size,
             "Response fits in single chunk, using batch transaction"
         );
-        send_batch_chunk_and_respond(config, request.request_id, request.yield_id, bytes).await
+        send_batch_chunk_and_respond(config, request.request_id, request.yield_id, bytes, &meta)
+            .await
     } else {
         let chunk_count = body_size.div_ceil(CHUNK_SIZE);
         info!(
@@ -249,8 +1226,146 @@ async fn handle_request(config: &Config, http: &Client, request: PendingRequest)
             chunk_size_bytes = CHUNK_SIZE,
             "Response body will be stored in chunks"
         );
-        store_response_chunks(config, request.request_id, &bytes).await?;
-        send_response(config, request.request_id, request.yield_id, None).await
+        if !store_response_chunks(config, request.request_id, &request.yield_id, &bytes).await? {
+            return Ok(());
+        }
+        send_response(config, request.request_id, request.yield_id, None, &meta).await
+    }
+}
+
+/// Perform the upstream HTTP request, retrying per `config.fetch_retry`. Connection failures and
+/// retryable status codes are retried for every method; timeouts and broken bodies only for
+/// idempotent ones, since the upstream may already have acted on the request. `rendered` holds
+/// the URL, headers and body with secrets substituted; errors leave its URL out.
+async fn fetch_upstream(
+    config: &Config,
+    http: &Client,
+    request: &PendingRequest,
+    rendered: &Rendered,
+) -> std::result::Result<(ResponseMeta, Vec<u8>), (FetchErrorKind, String)> {
+    let request_id = request.request_id;
+    let policy = &config.fetch_retry;
+    let limit = request
+        .max_response_bytes
+        .map_or(config.max_response_bytes, |limit| {
+            limit.min(config.max_response_bytes)
+        });
+    let mut attempt = 1;
+
+    loop {
+        let fetch_start = Instant::now();
+        let mut builder = http.request(request.method.as_reqwest(), rendered.url.clone());
+        for (name, value) in &rendered.headers {
+            builder = builder.header(name.as_str(), value.as_str());
+        }
+        if let Some(body) = &rendered.body {
+            builder = builder.body(body.clone());
+        }
+
+        let can_retry = attempt < policy.max_attempts;
+        let failure = match builder.send().await {
+            Ok(response) if can_retry && policy.retries_status(response.status().as_u16()) => {
+                let retry_after = response
+                    .headers()
+                    .get(reqwest::header::RETRY_AFTER)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| parse_retry_after(value, std::time::SystemTime::now()));
+                let delay = policy.backoff(attempt, retry_after);
+                warn!(
+                    request_id,
+                    attempt,
+                    max_attempts = policy.max_attempts,
+                    status = response.status().as_u16(),
+                    retry_after_ms = retry_after.map(|d| d.as_millis()),
+                    delay_ms = delay.as_millis(),
+                    "Upstream returned a retryable status, retrying"
+                );
+                sleep(delay).await;
+                attempt += 1;
+                continue;
+            }
+            Ok(response) => {
+                let mut meta = ResponseMeta::from_response(&response);
+                // Forwarded headers end up on chain; `location` can echo a secret from the URL.
+                for header in &mut meta.headers {
+                    header.value = rendered.redact(&header.value);
+                }
+                info!(
+                    request_id,
+                    url = %request.url,
+                    attempt,
+                    status = meta.status_code,
+                    forwarded_headers = meta.headers.len(),
+                    elapsed_ms = fetch_start.elapsed().as_millis(),
+                    "HTTP request completed"
+                );
+                match read_body(response, limit).await {
+                    Ok(bytes) => return Ok((meta, bytes)),
+                    Err(failure) => failure,
+                }
+            }
+            Err(err) => {
+                let err = if rendered.uses_secrets() {
+                    err.without_url()
+                } else {
+                    err
+                };
+                (FetchErrorKind::from_reqwest(&err), describe_error(&err))
+            }
+        };
+
+        let retryable = match failure.0 {
+            FetchErrorKind::Connection => true,
+            FetchErrorKind::Timeout | FetchErrorKind::Body => request.method.is_idempotent(),
+            _ => false,
+        };
+        if !(retryable && can_retry) {
+            return Err(failure);
+        }
+
+        let delay = policy.backoff(attempt, None);
+        warn!(
+            request_id,
+            attempt,
+            max_attempts = policy.max_attempts,
+            kind = ?failure.0,
+            error = %failure.1,
+            delay_ms = delay.as_millis(),
+            "HTTP request failed, retrying"
+        );
+        sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+/// Read the response body, giving up as soon as it is known to exceed `limit` bytes instead of
+/// buffering all of it.
+async fn read_body(
+    mut response: reqwest::Response,
+    limit: u64,
+) -> std::result::Result<Vec<u8>, (FetchErrorKind, String)> {
+    let too_large = || {
+        (
+            FetchErrorKind::ResponseTooLarge,
+            format!("response body exceeds {limit} bytes"),
+        )
+    };
+    if response
+        .content_length()
+        .is_some_and(|length| length > limit)
+    {
+        return Err(too_large());
+    }
+
+    let mut body = Vec::new();
+    loop {
+        match response.chunk().await {
+            Ok(Some(chunk)) if (body.len() + chunk.len()) as u64 > limit => return Err(too_large()),
+            Ok(Some(chunk)) => body.extend_from_slice(&chunk),
+            Ok(None) => return Ok(body),
+            // The URL may carry rendered secrets.
+            Err(err) => return Err((FetchErrorKind::Body, describe_error(&err.without_url()))),
+        }
     }
 }
 
@@ -259,36 +1374,45 @@ async fn send_response(
     request_id: u64,
     yield_id: Vec<u8>,
     body: Option<Vec<u8>>,
+    meta: &ResponseMeta,
 ) -> Result<()> {
     let body_size = body.as_ref().map(|b| b.len());
     info!(
         request_id,
         body_size_bytes = body_size,
         has_inline_body = body.is_some(),
+        status_code = meta.status_code,
         "Submitting 'respond' transaction"
     );
 
+    let args = &respond_args(request_id, &yield_id, body.as_deref(), meta)?;
     let tx_start = Instant::now();
-    let outcome = Contract(config.contract_id.clone())
-        .call_function(
-            "respond",
-            json!({
-                "request_id": request_id,
-                "yield_id": yield_id,
-                "body": body,
-            }),
-        )
-        .context("serializing respond args")?
-        .transaction()
-        .gas(NearGas::from_tgas(50))
-        .with_signer(config.relayer_id.clone(), config.signer.clone())
-        .wait_until(TxExecutionStatus::Executed)
-        .send_to(&config.network)
-        .await?;
+    let outcome = transact_with_gas(
+        "respond",
+        request_id,
+        gas::respond_gas(meta.body_len, body_size.unwrap_or(0) as u64),
+        gas::MAX_TRANSACTION_GAS,
+        |gas| async move {
+            retry(&config.tx_retry, "respond", request_id, || async move {
+                Contract(config.contract_id.clone())
+                    .call_function_raw("respond_borsh", args.clone())
+                    .transaction()
+                    .gas(gas)
+                    .with_signer(config.relayer_id.clone(), config.next_signer())
+                    .wait_until(TxExecutionStatus::Executed)
+                    .send_to(&config.network)
+                    .await
+                    .context("submitting respond transaction")
+            })
+            .await
+            .map(|outcome| outcome.into_result())
+        },
+    )
+    .await?;
 
     let tx_elapsed = tx_start.elapsed();
 
-    match outcome.into_result() {
+    match outcome {
         Ok(_) => {
             info!(
                 request_id,
@@ -297,6 +1421,16 @@ async fn send_response(
             );
             Ok(())
         }
+        Err(failure) if is_stale_request_failure(&failure) => {
+            info!(
+                request_id,
+                "Request was already resolved (cancelled or answered by another relayer)"
+            );
+            Ok(())
+        }
+        Err(failure) if is_insufficient_deposit_failure(&failure) => {
+            fail_insufficient_deposit(config, request_id, yield_id).await
+        }
         Err(failure) => {
             error!(
                 request_id,
@@ -309,63 +1443,171 @@ async fn send_response(
     }
 }
 
-async fn store_response_chunks(config: &Config, request_id: u64, body: &[u8]) -> Result<()> {
+async fn send_error(
+    config: &Config,
+    request_id: u64,
+    yield_id: Vec<u8>,
+    kind: FetchErrorKind,
+    message: String,
+) -> Result<()> {
+    info!(
+        request_id,
+        kind = ?kind,
+        "Submitting 'respond_error' transaction"
+    );
+
+    let args = &json!({
+        "request_id": request_id,
+        "yield_id": yield_id,
+        "error_kind": kind,
+        "message": truncate_error_message(message),
+    });
+    let tx_start = Instant::now();
+    let outcome = retry(
+        &config.tx_retry,
+        "respond_error",
+        request_id,
+        || async move {
+            Contract(config.contract_id.clone())
+                .call_function("respond_error", args.clone())
+                .context("serializing respond_error args")?
+                .transaction()
+                .gas(NearGas::from_tgas(30))
+                .with_signer(config.relayer_id.clone(), config.next_signer())
+                .wait_until(TxExecutionStatus::Executed)
+                .send_to(&config.network)
+                .await
+                .context("submitting respond_error transaction")
+        },
+    )
+    .await?;
+
+    let tx_elapsed = tx_start.elapsed();
+
+    match outcome.into_result() {
+        Ok(_) => {
+            info!(
+                request_id,
+                elapsed_ms = tx_elapsed.as_millis(),
+                "Error response transaction succeeded"
+            );
+            Ok(())
+        }
+        Err(failure) if is_stale_request_failure(&failure) => {
+            info!(
+                request_id,
+                "Request was already resolved (cancelled or answered by another relayer)"
+            );
+            Ok(())
+        }
+        Err(failure) => {
+            error!(
+                request_id,
+                error = ?failure,
+                elapsed_ms = tx_elapsed.as_millis(),
+                "Error response transaction failed"
+            );
+            Err(anyhow!("respond_error failed: {:?}", failure))
+        }
+    }
+}
+
+/// Upload `body` in chunks, resuming after the part a previous attempt already stored if it
+/// matches the start of `body`. Returns `false` if the request stopped being pending midway or
+/// was failed because its deposit ran out.
+async fn store_response_chunks(
+    config: &Config,
+    request_id: u64,
+    yield_id: &[u8],
+    body: &[u8],
+) -> Result<bool> {
     let total_chunks = body.len().div_ceil(CHUNK_SIZE);
+    let resume_from = match fetch_response_progress(config, request_id).await? {
+        Some(progress) => resume_offset(body, &progress),
+        None => {
+            info!(request_id, "Request is no longer pending, skipping upload");
+            return Ok(false);
+        }
+    };
     info!(
         request_id,
         total_chunks,
         total_size_bytes = body.len(),
+        resume_from,
         "Starting to store response chunks"
     );
 
-    let mut first = true;
-    let mut chunk_index = 0;
-
-    for chunk in body.chunks(CHUNK_SIZE) {
-        chunk_index += 1;
+    for (chunk_index, offset) in (resume_from..body.len()).step_by(CHUNK_SIZE).enumerate() {
+        let chunk = &body[offset..body.len().min(offset + CHUNK_SIZE)];
+        let chunk_index = chunk_index + 1;
         debug!(
             request_id,
             chunk_index,
-            total_chunks,
+            offset,
             chunk_size_bytes = chunk.len(),
-            is_first = first,
             "Submitting chunk transaction"
         );
 
+        let args = &chunk_args(request_id, chunk, offset as u64)?;
         let tx_start = Instant::now();
-        let outcome = Contract(config.contract_id.clone())
-            .call_function(
-                "store_response_chunk",
-                json!({
-                    "request_id": request_id,
-                    "data": chunk,
-                    "append": !first,
-                }),
-            )?
-            .transaction()
-            .gas(NearGas::from_tgas(100))
-            .with_signer(config.relayer_id.clone(), config.signer.clone())
-            .wait_until(TxExecutionStatus::Executed)
-            .send_to(&config.network)
-            .await?;
+        let outcome = transact_with_gas(
+            "store_response_chunk",
+            request_id,
+            gas::chunk_gas(chunk.len() as u64),
+            gas::MAX_TRANSACTION_GAS,
+            |gas| async move {
+                retry(
+                    &config.tx_retry,
+                    "store_response_chunk",
+                    request_id,
+                    || async move {
+                        Contract(config.contract_id.clone())
+                            .call_function_raw("store_response_chunk_borsh", args.clone())
+                            .transaction()
+                            .gas(gas)
+                            .with_signer(config.relayer_id.clone(), config.next_signer())
+                            .wait_until(TxExecutionStatus::Executed)
+                            .send_to(&config.network)
+                            .await
+                            .context("submitting store_response_chunk transaction")
+                    },
+                )
+                .await
+                .map(|outcome| outcome.into_result())
+            },
+        )
+        .await?;
 
         let tx_elapsed = tx_start.elapsed();
 
-        match outcome.into_result() {
+        match outcome {
             Ok(_) => {
                 debug!(
                     request_id,
                     chunk_index,
-                    total_chunks,
+                    offset,
                     elapsed_ms = tx_elapsed.as_millis(),
                     "Chunk transaction succeeded"
                 );
             }
+            Err(failure) if is_stale_request_failure(&failure) => {
+                info!(
+                    request_id,
+                    chunk_index,
+                    "Request is no longer pending (cancelled or answered by another relayer), stopping upload"
+                );
+                return Ok(false);
+            }
+            Err(failure) if is_insufficient_deposit_failure(&failure) => {
+                fail_insufficient_deposit(config, request_id, yield_id.to_vec()).await?;
+                return Ok(false);
+            }
             Err(failure) => {
+                // The next attempt resumes from whatever the contract confirmed.
                 error!(
                     request_id,
                     chunk_index,
-                    total_chunks,
+                    offset,
                     error = ?failure,
                     elapsed_ms = tx_elapsed.as_millis(),
                     "Chunk transaction failed"
@@ -373,8 +1615,6 @@ async fn store_response_chunks(config: &Config, request_id: u64, body: &[u8]) ->
                 return Err(anyhow!("store_response_chunk failed: {:?}", failure));
             }
         }
-
-        first = false;
     }
 
     info!(
@@ -383,7 +1623,110 @@ async fn store_response_chunks(config: &Config, request_id: u64, body: &[u8]) ->
         "All chunks stored successfully"
     );
 
-    Ok(())
+    Ok(true)
+}
+
+#[derive(Deserialize)]
+struct ResponseProgress {
+    length: u64,
+    sha256: Vec<u8>,
+}
+
+async fn fetch_response_progress(
+    config: &Config,
+    request_id: u64,
+) -> Result<Option<ResponseProgress>> {
+    let response: Data<Option<ResponseProgress>> = Contract(config.contract_id.clone())
+        .call_function(
+            "get_response_progress",
+            json!({ "request_id": request_id, "relayer": config.relayer_id }),
+        )
+        .context("serializing get_response_progress args")?
+        .read_only()
+        .fetch_from(&config.network)
+        .await?;
+
+    Ok(response.data)
+}
+
+/// Where to continue an upload of `body` given what the contract already stored. The stored bytes
+/// are only kept if they are a prefix of `body`, so a changed upstream response starts over.
+fn resume_offset(body: &[u8], progress: &ResponseProgress) -> usize {
+    let Ok(length) = usize::try_from(progress.length) else {
+        return 0;
+    };
+    match body.get(..length) {
+        Some(prefix) if length > 0 && Sha256::digest(prefix).as_slice() == progress.sha256 => {
+            length
+        }
+        _ => 0,
+    }
+}
+
+/// Submit a transaction with `gas` through `submit`, resubmitting it with more gas, up to
+/// `limit`, while it fails with `GasExceeded`. A failed transaction's state changes are
+/// reverted, so resubmitting it is safe.
+async fn transact_with_gas<T, E, F, Fut>(
+    what: &str,
+    request_id: u64,
+    mut gas: NearGas,
+    limit: NearGas,
+    mut submit: F,
+) -> Result<Result<T, E>>
+where
+    E: Serialize,
+    F: FnMut(NearGas) -> Fut,
+    Fut: std::future::Future<Output = Result<Result<T, E>>>,
+{
+    loop {
+        match submit(gas).await? {
+            Err(failure) if gas::is_gas_exceeded(&failure) => match gas::escalate(gas, limit) {
+                Some(more) => {
+                    warn!(
+                        request_id,
+                        what,
+                        gas_tgas = gas.as_tgas(),
+                        next_gas_tgas = more.as_tgas(),
+                        "Transaction ran out of gas, retrying with more"
+                    );
+                    gas = more;
+                }
+                None => return Ok(Err(failure)),
+            },
+            result => return Ok(result),
+        }
+    }
+}
+
+/// Borsh args of `store_response_chunk_borsh`.
+fn chunk_args(request_id: u64, data: &[u8], offset: u64) -> Result<Vec<u8>> {
+    Ok(borsh::to_vec(&(request_id, data, offset))?)
+}
+
+/// Borsh args of `respond_borsh`, in the order of the contract method's parameters.
+fn respond_args(
+    request_id: u64,
+    yield_id: &[u8],
+    body: Option<&[u8]>,
+    meta: &ResponseMeta,
+) -> Result<Vec<u8>> {
+    Ok(borsh::to_vec(&(
+        request_id,
+        yield_id,
+        body,
+        Some(meta.status_code),
+        Some(&meta.headers),
+        Some(&meta.body_hash),
+        Some(meta.body_len),
+        &meta.extraction,
+        Some(meta.body_encoding),
+    ))?)
+}
+
+fn gzip(body: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(body)?;
+    Ok(encoder.finish()?)
 }
 
 async fn send_batch_chunk_and_respond(
@@ -391,6 +1734,7 @@ async fn send_batch_chunk_and_respond(
     request_id: u64,
     yield_id: Vec<u8>,
     data: Vec<u8>,
+    meta: &ResponseMeta,
 ) -> Result<()> {
     let data_size = data.len();
     info!(
@@ -399,36 +1743,52 @@ async fn send_batch_chunk_and_respond(
         "Submitting batch transaction: store_response_chunk + respond"
     );
 
+    let chunk_args = &chunk_args(request_id, &data, 0)?;
+    let respond_args = &respond_args(request_id, &yield_id, None, meta)?;
+    // Escalation raises the transaction's total gas; the actions keep their estimated shares.
+    let chunk_estimate = gas::chunk_gas(data_size as u64).as_gas();
+    let respond_estimate = gas::respond_gas(data_size as u64, 0).as_gas();
+    let total = NearGas::from_gas(chunk_estimate + respond_estimate);
+
     let tx_start = Instant::now();
 
-    let outcome = Transaction::construct(config.relayer_id.clone(), config.contract_id.clone())
-        .add_action(Action::FunctionCall(Box::new(FunctionCallAction {
-            method_name: "store_response_chunk".to_string(),
-            args: serde_json::to_vec(&json!({
-                "request_id": request_id,
-                "data": data,
-                "append": false,
-            }))?,
-            gas: NearGas::from_tgas(250),
-            deposit: Default::default(),
-        })))
-        .add_action(Action::FunctionCall(Box::new(FunctionCallAction {
-            method_name: "respond".to_string(),
-            args: serde_json::to_vec(&json!({
-                "request_id": request_id,
-                "yield_id": yield_id,
-                "body": json!(null),
-            }))?,
-            gas: NearGas::from_tgas(50),
-            deposit: Default::default(),
-        })))
-        .with_signer(config.signer.clone())
-        .send_to(&config.network)
-        .await?;
+    let outcome = transact_with_gas(
+        "batch",
+        request_id,
+        total,
+        gas::MAX_TRANSACTION_GAS,
+        |total| async move {
+            let chunk_gas = (total.as_gas() as u128 * chunk_estimate as u128
+                / (chunk_estimate + respond_estimate) as u128) as u64;
+            let respond_gas = total.as_gas() - chunk_gas;
+            retry(&config.tx_retry, "batch", request_id, || async move {
+                Transaction::construct(config.relayer_id.clone(), config.contract_id.clone())
+                    .add_action(Action::FunctionCall(Box::new(FunctionCallAction {
+                        method_name: "store_response_chunk_borsh".to_string(),
+                        args: chunk_args.clone(),
+                        gas: NearGas::from_gas(chunk_gas),
+                        deposit: Default::default(),
+                    })))
+                    .add_action(Action::FunctionCall(Box::new(FunctionCallAction {
+                        method_name: "respond_borsh".to_string(),
+                        args: respond_args.clone(),
+                        gas: NearGas::from_gas(respond_gas),
+                        deposit: Default::default(),
+                    })))
+                    .with_signer(config.signer.clone())
+                    .send_to(&config.network)
+                    .await
+                    .context("submitting batch transaction")
+            })
+            .await
+            .map(|outcome| outcome.into_result())
+        },
+    )
+    .await?;
 
     let tx_elapsed = tx_start.elapsed();
 
-    match outcome.into_result() {
+    match outcome {
         Ok(_) => {
             info!(
                 request_id,
@@ -437,6 +1797,16 @@ async fn send_batch_chunk_and_respond(
             );
             Ok(())
         }
+        Err(failure) if is_stale_request_failure(&failure) => {
+            info!(
+                request_id,
+                "Request was already resolved (cancelled or answered by another relayer)"
+            );
+            Ok(())
+        }
+        Err(failure) if is_insufficient_deposit_failure(&failure) => {
+            fail_insufficient_deposit(config, request_id, yield_id).await
+        }
         Err(failure) => {
             error!(
                 request_id,
@@ -448,3 +1818,82 @@ async fn send_batch_chunk_and_respond(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resume_offset_requires_a_matching_prefix() {
+        let body = b"hello world";
+        let progress = |stored: &[u8]| ResponseProgress {
+            length: stored.len() as u64,
+            sha256: Sha256::digest(stored).to_vec(),
+        };
+
+        assert_eq!(resume_offset(body, &progress(b"hello ")), 6);
+        assert_eq!(resume_offset(body, &progress(b"hello world")), 11);
+        assert_eq!(resume_offset(body, &progress(b"")), 0);
+        assert_eq!(resume_offset(body, &progress(b"howdy ")), 0);
+        assert_eq!(resume_offset(body, &progress(b"hello world, again")), 0);
+    }
+
+    #[test]
+    fn error_messages_are_truncated_on_a_char_boundary() {
+        let message = format!("{}é", "a".repeat(MAX_ERROR_MESSAGE_LEN - 1));
+        assert_eq!(
+            truncate_error_message(message),
+            "a".repeat(MAX_ERROR_MESSAGE_LEN - 1)
+        );
+        assert_eq!(truncate_error_message("short".to_string()), "short");
+    }
+
+    #[test]
+    fn chunks_of_a_full_body_fit_the_gas_limit() {
+        let chunk_len = CHUNK_SIZE as u64;
+        assert!(chunk_len <= CONTRACT_MAX_RESPONSE_BYTES);
+        let chunk = gas::chunk_gas(chunk_len);
+        assert!(chunk.as_gas() <= gas::MAX_TRANSACTION_GAS.as_gas() / 2);
+        let batch = chunk.as_gas() + gas::respond_gas(chunk_len, 0).as_gas();
+        assert!(batch <= gas::MAX_TRANSACTION_GAS.as_gas());
+    }
+
+    #[test]
+    fn chunk_args_carry_raw_bytes() {
+        let args = chunk_args(7, b"abc", 300).unwrap();
+        let mut expected = 7u64.to_le_bytes().to_vec();
+        expected.extend_from_slice(&3u32.to_le_bytes());
+        expected.extend_from_slice(b"abc");
+        expected.extend_from_slice(&300u64.to_le_bytes());
+        assert_eq!(args, expected);
+    }
+
+    #[test]
+    fn gzip_round_trips() {
+        use std::io::Read;
+
+        let body = br#"{"list":[{"name":"Barcelona"},{"name":"Barcelona"}]}"#.repeat(50);
+        let compressed = gzip(&body).unwrap();
+        assert!(compressed.len() < body.len());
+
+        let mut decompressed = Vec::new();
+        flate2::read::GzDecoder::new(compressed.as_slice())
+            .read_to_end(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, body);
+    }
+
+    #[test]
+    fn in_flight_claims_are_exclusive_until_released() {
+        let in_flight = InFlight::default();
+
+        let guard = in_flight.claim(3).expect("first claim succeeds");
+        assert!(in_flight.claim(3).is_none());
+        assert!(in_flight.contains(3));
+        assert!(in_flight.claim(4).is_some());
+
+        drop(guard);
+        assert!(!in_flight.contains(3));
+        assert!(in_flight.claim(3).is_some());
+    }
+}
@@ -19,5 +19,20 @@ async fn main() -> Result<()> {
         .init();
 
     let config = relayer::Config::from_env()?;
+
+    // `relayer provision-keys [count]` adds function-call access keys for the key pool and prints
+    // them in the form RELAYER_ACCESS_KEYS expects.
+    let mut args = std::env::args().skip(1);
+    if args.next().as_deref() == Some("provision-keys") {
+        let count = match args.next() {
+            Some(count) => count.parse()?,
+            None => 4,
+        };
+        let keys = relayer::provision_access_keys(&config, count, None).await?;
+        let keys: Vec<String> = keys.iter().map(ToString::to_string).collect();
+        println!("RELAYER_ACCESS_KEYS={}", keys.join(","));
+        return Ok(());
+    }
+
     relayer::run(config).await
 }
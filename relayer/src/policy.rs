@@ -0,0 +1,315 @@
+//! Which URLs the relayer is willing to fetch. Contracts choose the URL, so without these checks
+//! anyone could make the relayer reach cloud metadata endpoints, localhost or the private
+//! network it runs in.
+
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::redirect;
+use url::Url;
+
+const MAX_REDIRECTS: usize = 10;
+
+/// A URL the policy refuses to fetch. Surfaces as `FetchErrorKind::PolicyRejected`.
+#[derive(Debug)]
+pub struct PolicyError(String);
+
+impl fmt::Display for PolicyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "blocked by relayer URL policy: {}", self.0)
+    }
+}
+
+impl std::error::Error for PolicyError {}
+
+/// A host name, or `*.domain` for any subdomain of `domain`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HostPattern(String);
+
+impl HostPattern {
+    pub fn new(pattern: &str) -> Self {
+        Self(pattern.trim().trim_end_matches('.').to_ascii_lowercase())
+    }
+
+    pub fn matches(&self, host: &str) -> bool {
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        match self.0.strip_prefix("*.") {
+            Some(domain) => host
+                .strip_suffix(domain)
+                .is_some_and(|rest| rest.ends_with('.')),
+            None => host == self.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct UrlPolicy {
+    pub allowed_schemes: Vec<String>,
+    /// When non-empty, only hosts matching one of these patterns are fetched.
+    pub allow_hosts: Vec<HostPattern>,
+    /// Hosts that are never fetched, checked before `allow_hosts`.
+    pub deny_hosts: Vec<HostPattern>,
+    /// Refuse loopback, private, link-local and other non-public addresses, both as literal
+    /// hosts and as DNS results.
+    pub block_private_ips: bool,
+}
+
+impl Default for UrlPolicy {
+    fn default() -> Self {
+        Self {
+            allowed_schemes: vec!["http".to_string(), "https".to_string()],
+            allow_hosts: Vec::new(),
+            deny_hosts: Vec::new(),
+            block_private_ips: true,
+        }
+    }
+}
+
+impl UrlPolicy {
+    /// Parse a comma-separated list of host patterns.
+    pub fn parse_hosts(list: &str) -> Vec<HostPattern> {
+        list.split(',')
+            .filter(|pattern| !pattern.trim().is_empty())
+            .map(HostPattern::new)
+            .collect()
+    }
+
+    pub fn check(&self, url: &Url) -> Result<(), PolicyError> {
+        if !self
+            .allowed_schemes
+            .iter()
+            .any(|scheme| scheme.eq_ignore_ascii_case(url.scheme()))
+        {
+            return Err(PolicyError(format!(
+                "scheme {:?} is not allowed",
+                url.scheme()
+            )));
+        }
+
+        let host = match url.host() {
+            Some(url::Host::Domain(domain)) => domain.to_string(),
+            Some(url::Host::Ipv4(ip)) => {
+                self.check_ip(IpAddr::V4(ip))?;
+                ip.to_string()
+            }
+            Some(url::Host::Ipv6(ip)) => {
+                self.check_ip(IpAddr::V6(ip))?;
+                ip.to_string()
+            }
+            None => return Err(PolicyError("URL has no host".to_string())),
+        };
+
+        if self.deny_hosts.iter().any(|pattern| pattern.matches(&host)) {
+            return Err(PolicyError(format!("host {host} is denied")));
+        }
+        if !self.allow_hosts.is_empty()
+            && !self
+                .allow_hosts
+                .iter()
+                .any(|pattern| pattern.matches(&host))
+        {
+            return Err(PolicyError(format!("host {host} is not allowed")));
+        }
+
+        Ok(())
+    }
+
+    fn check_ip(&self, ip: IpAddr) -> Result<(), PolicyError> {
+        if self.block_private_ips && !is_public(ip) {
+            return Err(PolicyError(format!("address {ip} is not public")));
+        }
+        Ok(())
+    }
+
+    /// Redirect policy that re-checks every hop against this policy.
+    pub fn redirect_policy(self: &Arc<Self>) -> redirect::Policy {
+        let policy = Arc::clone(self);
+        redirect::Policy::custom(move |attempt| {
+            if attempt.previous().len() >= MAX_REDIRECTS {
+                attempt.error("too many redirects")
+            } else if let Err(err) = policy.check(attempt.url()) {
+                attempt.error(err)
+            } else {
+                attempt.follow()
+            }
+        })
+    }
+}
+
+/// Whether `ip` is a globally routable address.
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(mapped) => is_public_v4(mapped),
+            None => is_public_v6(ip),
+        },
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        // 0.0.0.0/8, shared address space 100.64.0.0/10, benchmarking 198.18.0.0/15 and the
+        // reserved 240.0.0.0/4.
+        || a == 0
+        || (a == 100 && (64..128).contains(&b))
+        || (a == 198 && (18..20).contains(&b))
+        || a >= 240)
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    if let Some(embedded) = embedded_v4(ip) {
+        return is_public_v4(embedded);
+    }
+    let first = ip.segments()[0];
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        // Unique local fc00::/7, link-local fe80::/10 and documentation 2001:db8::/32.
+        || (first & 0xfe00) == 0xfc00
+        || (first & 0xffc0) == 0xfe80
+        || (first == 0x2001 && ip.segments()[1] == 0x0db8))
+}
+
+/// The IPv4 address a NAT64 `64:ff9b::/96`, 6to4 `2002::/16` or IPv4-compatible `::a.b.c.d`
+/// address reaches, so it is checked like the IPv4 address itself. `::` and `::1` come out as
+/// `0.0.0.0` and `0.0.0.1`, which are blocked as well.
+fn embedded_v4(ip: Ipv6Addr) -> Option<Ipv4Addr> {
+    let octets = ip.octets();
+    let v4 = |at: usize| Ipv4Addr::new(octets[at], octets[at + 1], octets[at + 2], octets[at + 3]);
+    match ip.segments() {
+        [0x64, 0xff9b, 0, 0, 0, 0, _, _] | [0, 0, 0, 0, 0, 0, _, _] => Some(v4(12)),
+        [0x2002, ..] => Some(v4(2)),
+        _ => None,
+    }
+}
+
+/// DNS resolver that drops non-public addresses, so a public host name pointing at a private
+/// address can't be used to get around the policy.
+pub struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let host = name.as_str().to_string();
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), 0))
+                .await?
+                .filter(|addr| is_public(addr.ip()))
+                .collect();
+            if addrs.is_empty() {
+                let err: Box<dyn std::error::Error + Send + Sync> = Box::new(PolicyError(format!(
+                    "{host} does not resolve to a public address"
+                )));
+                return Err(err);
+            }
+            let addrs: Addrs = Box::new(addrs.into_iter());
+            Ok(addrs)
+        })
+    }
+}
+
+/// Whether `err` or any of its sources is a [`PolicyError`].
+pub fn is_policy_error(err: &(dyn std::error::Error + 'static)) -> bool {
+    let mut current = Some(err);
+    while let Some(err) = current {
+        if err.is::<PolicyError>() {
+            return true;
+        }
+        current = err.source();
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(s: &str) -> Url {
+        Url::parse(s).unwrap()
+    }
+
+    #[test]
+    fn host_patterns() {
+        let wildcard = HostPattern::new("*.example.com");
+        assert!(wildcard.matches("api.example.com"));
+        assert!(wildcard.matches("a.b.example.com"));
+        assert!(!wildcard.matches("example.com"));
+        assert!(!wildcard.matches("badexample.com"));
+
+        let exact = HostPattern::new("Example.com.");
+        assert!(exact.matches("example.com"));
+        assert!(!exact.matches("api.example.com"));
+    }
+
+    #[test]
+    fn blocks_non_public_addresses() {
+        let policy = UrlPolicy::default();
+        for blocked in [
+            "http://169.254.169.254/latest/meta-data/",
+            "http://127.0.0.1:8080/",
+            "http://10.1.2.3/",
+            "http://192.168.0.1/",
+            "http://100.64.0.1/",
+            "http://0.0.0.0/",
+            "http://[::1]/",
+            "http://[fd00::1]/",
+            "http://[::ffff:127.0.0.1]/",
+            "http://[64:ff9b::7f00:1]/",
+            "http://[2002:7f00:1::]/",
+            "http://[::10.0.0.1]/",
+        ] {
+            assert!(
+                policy.check(&url(blocked)).is_err(),
+                "{blocked} should be blocked"
+            );
+        }
+        assert!(policy.check(&url("https://1.1.1.1/")).is_ok());
+        assert!(policy.check(&url("https://[64:ff9b::101:101]/")).is_ok());
+        assert!(policy.check(&url("https://[2002:101:101::]/")).is_ok());
+        assert!(policy.check(&url("https://example.com/")).is_ok());
+    }
+
+    #[test]
+    fn schemes_and_host_lists() {
+        let policy = UrlPolicy {
+            allow_hosts: UrlPolicy::parse_hosts("*.example.com, api.weather.org"),
+            deny_hosts: UrlPolicy::parse_hosts("internal.example.com"),
+            ..UrlPolicy::default()
+        };
+        assert!(policy.check(&url("https://api.example.com/x")).is_ok());
+        assert!(policy.check(&url("https://api.weather.org/")).is_ok());
+        assert!(policy.check(&url("https://internal.example.com/")).is_err());
+        assert!(policy.check(&url("https://other.org/")).is_err());
+        assert!(policy.check(&url("ftp://api.example.com/")).is_err());
+        assert!(policy.check(&url("file:///etc/passwd")).is_err());
+    }
+
+    #[test]
+    fn finds_policy_errors_in_source_chain() {
+        #[derive(Debug)]
+        struct Wrapper(PolicyError);
+        impl fmt::Display for Wrapper {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "wrapped")
+            }
+        }
+        impl std::error::Error for Wrapper {
+            fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+                Some(&self.0)
+            }
+        }
+
+        let err = Wrapper(PolicyError("nope".to_string()));
+        assert!(is_policy_error(&err));
+        assert!(!is_policy_error(&fmt::Error));
+    }
+}
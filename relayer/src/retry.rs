@@ -0,0 +1,226 @@
+//! Retry policies with exponential backoff, used for upstream fetches and for transaction
+//! submission.
+
+use std::collections::hash_map::RandomState;
+use std::fmt::Display;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::time::{Duration, SystemTime};
+
+use tokio::time::sleep;
+use tracing::warn;
+
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// Total attempts including the first one; `1` disables retries.
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled for every further one.
+    pub base_delay: Duration,
+    /// Upper bound for a single delay, including one requested through `Retry-After`.
+    pub max_delay: Duration,
+    /// Fraction of each delay that is randomized, from `0.0` to `1.0`, so relayers that failed
+    /// together don't retry in lockstep.
+    pub jitter: f64,
+    /// Upstream HTTP status codes worth another attempt. Unused for transactions.
+    pub retry_statuses: Vec<u16>,
+}
+
+impl RetryPolicy {
+    /// Default for upstream HTTP fetches.
+    pub fn fetch_default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(10),
+            jitter: 0.2,
+            retry_statuses: vec![408, 429, 500, 502, 503, 504],
+        }
+    }
+
+    /// Default for submitting transactions to the RPC node.
+    pub fn transaction_default() -> Self {
+        Self {
+            max_attempts: 4,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(15),
+            jitter: 0.2,
+            retry_statuses: Vec::new(),
+        }
+    }
+
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    pub fn with_base_delay(mut self, base_delay: Duration) -> Self {
+        self.base_delay = base_delay;
+        self
+    }
+
+    pub fn with_jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn with_retry_statuses(mut self, retry_statuses: Vec<u16>) -> Self {
+        self.retry_statuses = retry_statuses;
+        self
+    }
+
+    /// Parse a comma-separated list of HTTP status codes such as `429,503`. An empty list turns
+    /// off retrying on status codes.
+    pub fn parse_statuses(list: &str) -> Result<Vec<u16>, std::num::ParseIntError> {
+        list.split(',')
+            .map(str::trim)
+            .filter(|status| !status.is_empty())
+            .map(str::parse)
+            .collect()
+    }
+
+    pub fn retries_status(&self, status: u16) -> bool {
+        self.retry_statuses.contains(&status)
+    }
+
+    /// Delay to wait after failed attempt number `attempt` (starting at 1). A `retry_after`
+    /// requested by the server replaces the computed backoff.
+    pub fn backoff(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        if let Some(retry_after) = retry_after {
+            return retry_after.min(self.max_delay);
+        }
+        let exponent = attempt.saturating_sub(1).min(16);
+        let delay = self
+            .base_delay
+            .saturating_mul(1 << exponent)
+            .min(self.max_delay);
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        delay.mul_f64(1.0 - jitter * random_unit())
+    }
+}
+
+/// Parse a `Retry-After` header value, either delay-seconds or an HTTP date.
+pub fn parse_retry_after(value: &str, now: SystemTime) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let at = httpdate::parse_http_date(value).ok()?;
+    Some(at.duration_since(now).unwrap_or_default())
+}
+
+/// Run `op` until it succeeds or `policy` runs out of attempts, returning the last error.
+pub async fn retry<T, E, F, Fut>(
+    policy: &RetryPolicy,
+    what: &str,
+    request_id: u64,
+    mut op: F,
+) -> Result<T, E>
+where
+    E: Display,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let mut attempt = 1;
+    loop {
+        match op().await {
+            Ok(value) => return Ok(value),
+            Err(err) if attempt < policy.max_attempts => {
+                let delay = policy.backoff(attempt, None);
+                warn!(
+                    request_id,
+                    what,
+                    attempt,
+                    max_attempts = policy.max_attempts,
+                    delay_ms = delay.as_millis(),
+                    error = %err,
+                    "Attempt failed, retrying"
+                );
+                sleep(delay).await;
+                attempt += 1;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// A random number in `[0, 1)`, good enough for jitter without pulling in an RNG.
+fn random_unit() -> f64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u128(
+        SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos(),
+    );
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(1_000),
+            jitter: 0.0,
+            retry_statuses: Vec::new(),
+        };
+
+        assert_eq!(policy.backoff(1, None), Duration::from_millis(100));
+        assert_eq!(policy.backoff(2, None), Duration::from_millis(200));
+        assert_eq!(policy.backoff(4, None), Duration::from_millis(800));
+        assert_eq!(policy.backoff(5, None), Duration::from_millis(1_000));
+        assert_eq!(
+            policy.backoff(1, Some(Duration::from_secs(60))),
+            Duration::from_millis(1_000)
+        );
+    }
+
+    #[test]
+    fn jitter_only_shortens_the_delay() {
+        let policy = RetryPolicy::fetch_default();
+        for _ in 0..100 {
+            let delay = policy.backoff(2, None);
+            assert!(delay <= Duration::from_millis(1_000));
+            assert!(delay >= Duration::from_millis(800));
+        }
+    }
+
+    #[test]
+    fn parses_status_lists() {
+        assert_eq!(
+            RetryPolicy::parse_statuses("429, 503,504"),
+            Ok(vec![429, 503, 504])
+        );
+        assert_eq!(RetryPolicy::parse_statuses(""), Ok(Vec::new()));
+        assert!(RetryPolicy::parse_statuses("429,5xx").is_err());
+
+        let policy = RetryPolicy::fetch_default()
+            .with_retry_statuses(vec![503])
+            .with_jitter(2.0);
+        assert!(policy.retries_status(503));
+        assert!(!policy.retries_status(429));
+        assert_eq!(policy.jitter, 1.0);
+    }
+
+    #[test]
+    fn parses_retry_after() {
+        let now = httpdate::parse_http_date("Wed, 21 Oct 2015 07:28:00 GMT").unwrap();
+        assert_eq!(
+            parse_retry_after("120", now),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:30 GMT", now),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:27:00 GMT", now),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after("soon", now), None);
+    }
+}
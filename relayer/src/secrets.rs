@@ -0,0 +1,388 @@
+//! Named secrets substituted into requests at fetch time, so API keys never have to appear in
+//! the on-chain URL, headers or body. Requests reference them as `{{secret:name}}`.
+//!
+//! Secrets are loaded from a JSON file keyed by name:
+//!
+//! ```json
+//! {
+//!   "openweather": {
+//!     "env": "OPENWEATHER_API_KEY",
+//!     "allowed_callers": ["weather.testnet"],
+//!     "allowed_hosts": ["api.openweathermap.org"]
+//!   }
+//! }
+//! ```
+//!
+//! Each entry takes its value either inline (`"value"`) or from an environment variable
+//! (`"env"`). A secret is only substituted for requests from one of `allowed_callers` whose URL
+//! host matches one of `allowed_hosts`; an empty list allows nothing.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use url::Url;
+
+use crate::policy::HostPattern;
+
+const PLACEHOLDER_OPEN: &str = "{{secret:";
+const PLACEHOLDER_CLOSE: &str = "}}";
+
+/// A request that can't be rendered. Surfaces as `FetchErrorKind::PolicyRejected`; unknown and
+/// forbidden secrets read the same, so callers can't probe which names exist.
+#[derive(Debug)]
+pub struct SecretError(String);
+
+impl fmt::Display for SecretError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for SecretError {}
+
+#[derive(Clone, Debug)]
+pub struct Secret {
+    pub value: String,
+    pub allowed_callers: Vec<String>,
+    pub allowed_hosts: Vec<HostPattern>,
+}
+
+impl Secret {
+    fn permits(&self, caller: &str, host: &str) -> bool {
+        self.allowed_callers.iter().any(|allowed| allowed == caller)
+            && self
+                .allowed_hosts
+                .iter()
+                .any(|pattern| pattern.matches(host))
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SecretEntry {
+    #[serde(default)]
+    value: Option<String>,
+    #[serde(default)]
+    env: Option<String>,
+    #[serde(default)]
+    allowed_callers: Vec<String>,
+    #[serde(default)]
+    allowed_hosts: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SecretStore {
+    secrets: HashMap<String, Secret>,
+}
+
+impl SecretStore {
+    /// Load secrets from a JSON file in the format described in the module docs.
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("reading secrets file {}", path.display()))?;
+        Self::from_json(&contents).with_context(|| format!("parsing {}", path.display()))
+    }
+
+    pub fn from_json(contents: &str) -> Result<Self> {
+        let entries: HashMap<String, SecretEntry> = serde_json::from_str(contents)?;
+        let mut store = Self::default();
+        for (name, entry) in entries {
+            let value = match (entry.value, entry.env) {
+                (Some(value), None) => value,
+                (None, Some(var)) => std::env::var(&var)
+                    .with_context(|| format!("secret {name} reads unset variable {var}"))?,
+                _ => return Err(anyhow!("secret {name} needs exactly one of value or env")),
+            };
+            store = store.with_secret(
+                &name,
+                Secret {
+                    value,
+                    allowed_callers: entry.allowed_callers,
+                    allowed_hosts: entry
+                        .allowed_hosts
+                        .iter()
+                        .map(|host| HostPattern::new(host))
+                        .collect(),
+                },
+            );
+        }
+        Ok(store)
+    }
+
+    pub fn with_secret(mut self, name: &str, secret: Secret) -> Self {
+        self.secrets.insert(name.to_string(), secret);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.secrets.is_empty()
+    }
+
+    /// Substitute the secrets referenced in `url`, `headers` and `body`, after checking that
+    /// `caller` may send each of them to the URL's host. A secret that changes the host, or one
+    /// that would be sent over anything but `https`, is rejected.
+    pub fn render(
+        &self,
+        caller: &str,
+        url: &str,
+        headers: &[(String, String)],
+        body: Option<&[u8]>,
+    ) -> Result<Rendered, SecretError> {
+        let mut used = Vec::new();
+        let host = Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+            .ok_or_else(|| SecretError("URL has no host".to_string()))?;
+        let rendered_url = self.substitute(url, caller, &mut used)?;
+        let rendered_url = Url::parse(&rendered_url)
+            .ok()
+            .filter(|rendered| rendered.host_str() == Some(host.as_str()))
+            .ok_or_else(|| SecretError("secrets may not change the URL host".to_string()))?;
+
+        let headers = headers
+            .iter()
+            .map(|(name, value)| Ok((name.clone(), self.substitute(value, caller, &mut used)?)))
+            .collect::<Result<Vec<_>, SecretError>>()?;
+        // Binary bodies can't contain placeholders and are sent as they are.
+        let body = match body.map(std::str::from_utf8) {
+            Some(Ok(text)) => Some(self.substitute(text, caller, &mut used)?.into_bytes()),
+            Some(Err(_)) => body.map(<[u8]>::to_vec),
+            None => None,
+        };
+
+        if !used.is_empty() && rendered_url.scheme() != "https" {
+            return Err(SecretError("secrets are only sent over https".to_string()));
+        }
+
+        let mut values = Vec::new();
+        for name in used {
+            let secret = &self.secrets[&name];
+            if !secret.permits(caller, &host) {
+                return Err(unavailable(&name, caller));
+            }
+            values.push((name, secret.value.clone()));
+        }
+
+        Ok(Rendered {
+            url: rendered_url,
+            headers,
+            body,
+            secrets: values,
+        })
+    }
+
+    fn substitute(
+        &self,
+        template: &str,
+        caller: &str,
+        used: &mut Vec<String>,
+    ) -> Result<String, SecretError> {
+        let mut rendered = String::with_capacity(template.len());
+        let mut rest = template;
+        while let Some(start) = rest.find(PLACEHOLDER_OPEN) {
+            let after = &rest[start + PLACEHOLDER_OPEN.len()..];
+            let Some(end) = after.find(PLACEHOLDER_CLOSE) else {
+                break;
+            };
+            let name = after[..end].trim();
+            let secret = self
+                .secrets
+                .get(name)
+                .ok_or_else(|| unavailable(name, caller))?;
+            rendered.push_str(&rest[..start]);
+            rendered.push_str(&secret.value);
+            if !used.iter().any(|used| used == name) {
+                used.push(name.to_string());
+            }
+            rest = &after[end + PLACEHOLDER_CLOSE.len()..];
+        }
+        rendered.push_str(rest);
+        Ok(rendered)
+    }
+}
+
+fn unavailable(name: &str, caller: &str) -> SecretError {
+    SecretError(format!("secret {name} is not available to {caller}"))
+}
+
+/// A request with its secrets substituted.
+pub struct Rendered {
+    pub url: Url,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<Vec<u8>>,
+    /// Names and values of the secrets that were substituted.
+    secrets: Vec<(String, String)>,
+}
+
+impl Rendered {
+    pub fn uses_secrets(&self) -> bool {
+        !self.secrets.is_empty()
+    }
+
+    /// Replace any secret value in `text`, as is or percent-encoded, with its placeholder, for
+    /// messages and headers that end up on chain or in logs.
+    pub fn redact(&self, text: &str) -> String {
+        let mut redacted = text.to_string();
+        let mut seen = HashSet::new();
+        for (name, value) in &self.secrets {
+            if value.is_empty() || !seen.insert(value) {
+                continue;
+            }
+            let placeholder = format!("{PLACEHOLDER_OPEN}{name}{PLACEHOLDER_CLOSE}");
+            let encoded: String = url::form_urlencoded::byte_serialize(value.as_bytes()).collect();
+            redacted = redacted
+                .replace(value, &placeholder)
+                .replace(&encoded, &placeholder);
+        }
+        redacted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store() -> SecretStore {
+        SecretStore::from_json(
+            r#"{
+                "weather": {
+                    "value": "k3y",
+                    "allowed_callers": ["weather.near"],
+                    "allowed_hosts": ["api.weather.org"]
+                },
+                "token": {
+                    "value": "t0ken",
+                    "allowed_callers": ["weather.near", "other.near"],
+                    "allowed_hosts": ["*.example.com"]
+                }
+            }"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn substitutes_url_headers_and_body() {
+        let rendered = store()
+            .render(
+                "weather.near",
+                "https://api.weather.org/find?appid={{secret:weather}}",
+                &[("x-api-key".to_string(), "{{secret: weather}}".to_string())],
+                Some(br#"{"key":"{{secret:weather}}"}"#),
+            )
+            .unwrap();
+        assert_eq!(
+            rendered.url.as_str(),
+            "https://api.weather.org/find?appid=k3y"
+        );
+        assert_eq!(rendered.headers[0].1, "k3y");
+        assert_eq!(rendered.body.as_deref(), Some(&br#"{"key":"k3y"}"#[..]));
+        assert!(rendered.uses_secrets());
+        assert_eq!(
+            rendered.redact("error for https://api.weather.org/find?appid=k3y"),
+            "error for https://api.weather.org/find?appid={{secret:weather}}"
+        );
+    }
+
+    #[test]
+    fn requests_without_placeholders_are_untouched() {
+        let rendered = store()
+            .render(
+                "anyone.near",
+                "https://example.org/{{secret",
+                &[],
+                Some(&[0xff, 0xfe]),
+            )
+            .unwrap();
+        assert!(!rendered.uses_secrets());
+        assert_eq!(rendered.body.as_deref(), Some(&[0xff, 0xfe][..]));
+    }
+
+    #[test]
+    fn enforces_caller_and_host_allowlists() {
+        let store = store();
+        assert!(store
+            .render(
+                "other.near",
+                "https://api.weather.org/?k={{secret:weather}}",
+                &[],
+                None
+            )
+            .is_err());
+        assert!(store
+            .render(
+                "weather.near",
+                "https://evil.org/?k={{secret:weather}}",
+                &[],
+                None
+            )
+            .is_err());
+        assert!(store
+            .render(
+                "weather.near",
+                "https://api.weather.org/?k={{secret:missing}}",
+                &[],
+                None
+            )
+            .is_err());
+        assert!(store
+            .render(
+                "other.near",
+                "https://api.example.com/",
+                &[(
+                    "authorization".to_string(),
+                    "Bearer {{secret:token}}".to_string()
+                )],
+                None
+            )
+            .is_ok());
+    }
+
+    #[test]
+    fn secrets_require_https() {
+        let store = store();
+        assert!(store
+            .render(
+                "weather.near",
+                "http://api.weather.org/?k={{secret:weather}}",
+                &[],
+                None
+            )
+            .is_err());
+        assert!(store
+            .render("weather.near", "http://api.weather.org/", &[], None)
+            .is_ok());
+    }
+
+    #[test]
+    fn redacts_percent_encoded_values() {
+        let store = SecretStore::default().with_secret(
+            "key",
+            Secret {
+                value: "a b/c".to_string(),
+                allowed_callers: vec!["weather.near".to_string()],
+                allowed_hosts: vec![HostPattern::new("api.weather.org")],
+            },
+        );
+        let rendered = store
+            .render(
+                "weather.near",
+                "https://api.weather.org/",
+                &[("x-api-key".to_string(), "{{secret:key}}".to_string())],
+                None,
+            )
+            .unwrap();
+        assert_eq!(
+            rendered.redact("https://api.weather.org/next?k=a+b%2Fc"),
+            "https://api.weather.org/next?k={{secret:key}}"
+        );
+    }
+
+    #[test]
+    fn entries_need_one_value_source() {
+        assert!(SecretStore::from_json(r#"{"a": {"allowed_callers": []}}"#).is_err());
+        assert!(SecretStore::from_json(r#"{"a": {"value": "x", "env": "Y"}}"#).is_err());
+    }
+}
@@ -1,20 +1,476 @@
 use near_sdk::borsh::{BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::Base64VecU8;
 use near_sdk::serde::{Deserialize, Serialize};
 use near_sdk::serde_json;
-use near_sdk::store::IterableMap;
+use near_sdk::store::{IterableMap, IterableSet, LookupMap, TreeMap};
 use near_sdk::{
-    env, near, require, AccountId, BorshStorageKey, CryptoHash, Gas, GasWeight, PromiseResult,
+    env, near, require, AccountId, BorshStorageKey, CryptoHash, Gas, GasWeight, NearToken, Promise,
+    PromiseResult,
 };
 
 const YIELD_REGISTER: u64 = 0;
 const RESUME_GAS: Gas = Gas::from_tgas(20);
+const WITHDRAW_CALLBACK_GAS: Gas = Gas::from_tgas(5);
+const MAX_ERROR_MESSAGE_LEN: usize = 512;
+/// Hard cap on a stored response body. Keeps the JSON-encoded `FetchResult` under the 4 MB
+/// return data limit.
+const MAX_RESPONSE_BYTES: u64 = 1_000_000;
+/// Rough per-entry key and record overhead used by `estimate_deposit`.
+const REQUEST_STORAGE_OVERHEAD: u64 = 400;
+/// Key and record overhead of a relayer's body record, and of each chunk stored for it.
+const BODY_STORAGE_OVERHEAD: u64 = 300;
+const CHUNK_STORAGE_OVERHEAD: u64 = 150;
+/// Chunks per body whose overhead `estimate_deposit` and `storable_response_bytes` set aside: a
+/// full body in the relayer's chunks plus one resumed upload.
+const CHUNKS_PER_BODY: usize = 4;
+const DEFAULT_LIST_LIMIT: u32 = 50;
+const MAX_LIST_LIMIT: u32 = 200;
+const EVENT_STANDARD: &str = "http_fetch";
+const EVENT_VERSION: &str = "1.0.0";
+/// Blocks until the protocol resumes an unanswered yield with a timeout.
+const YIELD_TIMEOUT_BLOCKS: u64 = 200;
+/// Extra blocks after the yield timeout before `prune` treats a request whose callback never
+/// ran as stale.
+const STALE_REQUEST_GRACE_BLOCKS: u64 = 100;
+const DEFAULT_MAX_URL_LENGTH: u32 = 2048;
+const MAX_EXTRACT_POINTERS: usize = 32;
+const MAX_EXTRACT_POINTER_LEN: usize = 256;
+
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum HttpMethod {
+    #[default]
+    Get,
+    Post,
+    Put,
+    Patch,
+    Delete,
+    Head,
+}
+
+#[near(serializers = [borsh, json])]
+#[derive(Clone)]
+pub struct HttpHeader {
+    pub name: String,
+    pub value: String,
+}
+
+/// HTTP request spec the relayer should issue for `url`. Defaults to a bare GET.
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Default)]
+pub struct HttpRequest {
+    #[serde(default)]
+    pub method: HttpMethod,
+    #[serde(default)]
+    pub headers: Vec<HttpHeader>,
+    #[serde(default)]
+    pub body: Option<Vec<u8>>,
+}
+
+/// Optional per-request behaviour for `fetch`.
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Default)]
+pub struct FetchOptions {
+    /// Number of registered relayers that must report the same body hash before the
+    /// request completes. `None` accepts the first relayer's response.
+    #[serde(default)]
+    pub quorum: Option<u8>,
+    /// Blocks after `fetch` during which relayers may respond, at most the protocol's yield
+    /// timeout of 200 blocks (the default). Once it has passed responses are rejected and anyone
+    /// can `expire` the request.
+    #[serde(default)]
+    pub timeout_blocks: Option<u64>,
+    /// JSON Pointers (RFC 6901, e.g. `/main/temp`) the relayer extracts from a JSON response.
+    /// The stored body becomes a JSON object mapping each pointer to the value it selects, so
+    /// only the fields the caller needs are uploaded.
+    #[serde(default)]
+    pub extract: Option<Vec<String>>,
+    /// Largest upstream response, in bytes, the relayer downloads for this request before
+    /// failing it with `ResponseTooLarge`. The stored body is also capped at this size, and
+    /// never exceeds `max_response_bytes()`.
+    #[serde(default)]
+    pub max_response_bytes: Option<u64>,
+    /// Encoding `FetchResult.body` may be delivered in. The contract never decompresses, since
+    /// the callback's gas can't cover inflating a large body, so with the default, `Identity`,
+    /// the relayer uploads the plain body. With `Gzip` it uploads the body compressed, which
+    /// needs fewer chunk transactions and less storage, unless it doesn't shrink.
+    /// `FetchResult.body_encoding` reports what was delivered.
+    #[serde(default)]
+    pub response_encoding: Option<ResponseEncoding>,
+}
+
+impl FetchOptions {
+    /// Largest body this request accepts.
+    fn body_limit(&self) -> u64 {
+        self.max_response_bytes
+            .map_or(MAX_RESPONSE_BYTES, |limit| limit.min(MAX_RESPONSE_BYTES))
+    }
+}
+
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum ResponseEncoding {
+    #[default]
+    Identity,
+    Gzip,
+}
+
+/// What the relayer did with `FetchOptions::extract`.
+#[near(serializers = [borsh, json])]
+#[derive(Clone)]
+pub enum ExtractionOutcome {
+    /// The body is the extracted JSON object. Pointers listed in `missing` selected nothing and
+    /// map to `null`.
+    Extracted { missing: Vec<String> },
+    /// The response was not JSON; the body is returned unchanged.
+    Failed { message: String },
+}
+
+/// Minimum deposit `fetch` must attach: `base` plus `per_kilobyte` for every started KiB of
+/// url, headers, request body and context.
+#[near(serializers = [borsh, json])]
+#[derive(Clone)]
+pub struct FeeSchedule {
+    pub base: NearToken,
+    pub per_kilobyte: NearToken,
+}
+
+impl Default for FeeSchedule {
+    fn default() -> Self {
+        Self {
+            base: NearToken::from_yoctonear(0),
+            per_kilobyte: NearToken::from_yoctonear(0),
+        }
+    }
+}
+
+impl FeeSchedule {
+    fn fee_for(&self, request_bytes: u64) -> NearToken {
+        let kilobytes = u128::from(request_bytes.div_ceil(1024));
+        self.base
+            .saturating_add(self.per_kilobyte.saturating_mul(kilobytes))
+    }
+}
+
+/// Which URLs `fetch` accepts. Checked when the request is submitted, so callers fail fast
+/// instead of waiting for a relayer to refuse it.
+#[near(serializers = [borsh, json])]
+#[derive(Clone)]
+pub struct UrlPolicy {
+    /// Lowercase URL schemes, `http` and `https` by default.
+    pub allowed_schemes: Vec<String>,
+    /// Hosts `fetch` accepts; `*.example.com` matches any subdomain of `example.com`. Empty
+    /// allows every host.
+    pub allowed_domains: Vec<String>,
+    pub max_url_length: u32,
+}
+
+impl Default for UrlPolicy {
+    fn default() -> Self {
+        Self {
+            allowed_schemes: vec!["http".to_string(), "https".to_string()],
+            allowed_domains: Vec::new(),
+            max_url_length: DEFAULT_MAX_URL_LENGTH,
+        }
+    }
+}
+
+impl UrlPolicy {
+    fn normalized(self) -> Self {
+        let normalize = |entries: Vec<String>| {
+            entries
+                .into_iter()
+                .map(|entry| entry.trim().trim_end_matches('.').to_ascii_lowercase())
+                .filter(|entry| !entry.is_empty())
+                .collect()
+        };
+        Self {
+            allowed_schemes: normalize(self.allowed_schemes),
+            allowed_domains: normalize(self.allowed_domains),
+            max_url_length: self.max_url_length,
+        }
+    }
+
+    fn validate(&self, url: &str) {
+        require!(
+            url.len() <= self.max_url_length as usize,
+            format!("URL is longer than {} bytes", self.max_url_length)
+        );
+        let Some((scheme, host)) = url_scheme_and_host(url) else {
+            env::panic_str("URL must be absolute, with a scheme and a host");
+        };
+        require!(
+            self.allowed_schemes.contains(&scheme),
+            format!("URL scheme {scheme} is not allowed")
+        );
+        require!(
+            self.allowed_domains.is_empty()
+                || self
+                    .allowed_domains
+                    .iter()
+                    .any(|pattern| domain_matches(pattern, &host)),
+            format!("URL host {host} is not allowed")
+        );
+    }
+}
+
+/// The lowercased scheme and host of an absolute URL. Deliberately strict: anything the
+/// relayer's URL parser could read differently (whitespace, backslashes, percent-encoded
+/// hosts) is rejected rather than guessed at.
+fn url_scheme_and_host(url: &str) -> Option<(String, String)> {
+    if url
+        .chars()
+        .any(|c| c.is_whitespace() || c.is_control() || c == '\\')
+    {
+        return None;
+    }
+    let (scheme, rest) = url.split_once("://")?;
+    if scheme.is_empty()
+        || !scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+    {
+        return None;
+    }
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let host_and_port = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    let host = match host_and_port.strip_prefix('[') {
+        Some(bracketed) => bracketed.split_once(']')?.0,
+        None => host_and_port.split(':').next().unwrap_or_default(),
+    };
+    let host = host.trim_end_matches('.');
+    if host.is_empty() || host.contains('%') {
+        return None;
+    }
+    Some((scheme.to_ascii_lowercase(), host.to_ascii_lowercase()))
+}
+
+fn domain_matches(pattern: &str, host: &str) -> bool {
+    match pattern.strip_prefix("*.") {
+        Some(domain) => host
+            .strip_suffix(domain)
+            .is_some_and(|rest| rest.ends_with('.')),
+        None => host == pattern,
+    }
+}
+
+/// Key of chunk `index` of the body stored under `key` in `response_bodies`.
+fn chunk_key((request_id, relayer): &(u64, AccountId), index: usize) -> (u64, AccountId, u32) {
+    (*request_id, relayer.clone(), index as u32)
+}
+
+fn storage_cost(bytes: u64) -> NearToken {
+    env::storage_byte_cost().saturating_mul(u128::from(bytes))
+}
+
+fn request_size(url: &str, request: &HttpRequest, context: Option<&Vec<u8>>) -> u64 {
+    let headers: usize = request
+        .headers
+        .iter()
+        .map(|header| header.name.len() + header.value.len())
+        .sum();
+    let body = request.body.as_ref().map_or(0, Vec::len);
+    let context = context.map_or(0, Vec::len);
+    (url.len() + headers + body + context) as u64
+}
+
+/// A relayer's submission for a quorum request.
+#[near(serializers = [borsh, json])]
+#[derive(Clone)]
+pub struct RelayerVote {
+    pub relayer: AccountId,
+    /// sha256 of the body the relayer observed, `None` if it reported an error.
+    pub body_hash: Option<Vec<u8>>,
+    /// sha256 of everything the relayer reported: body hash, status code, headers, extraction
+    /// and body encoding. Votes only count towards the quorum when their digests are equal.
+    pub response_digest: Option<Vec<u8>>,
+    /// What went wrong, if the relayer reported an error.
+    pub error_kind: Option<FetchErrorKind>,
+}
+
+/// How a quorum request was decided, returned in `FetchResult`.
+#[near(serializers = [json])]
+#[derive(Clone)]
+pub struct ConsensusReport {
+    pub quorum: u8,
+    /// Body hash of the response that reached the quorum, if any.
+    pub accepted_hash: Option<Vec<u8>>,
+    pub votes: Vec<RelayerVote>,
+    /// Relayers whose vote differs from the accepted response.
+    pub mismatched: Vec<AccountId>,
+}
+
+impl ConsensusReport {
+    fn new(quorum: u8, accepted: Option<&ResponseMeta>, votes: Vec<RelayerVote>) -> Self {
+        let accepted_digest = accepted.map(ResponseMeta::digest);
+        let mismatched = votes
+            .iter()
+            .filter(|vote| vote.response_digest != accepted_digest)
+            .map(|vote| vote.relayer.clone())
+            .collect();
+        let accepted_hash = accepted.and_then(|meta| meta.body_hash.clone());
+        Self {
+            quorum,
+            accepted_hash,
+            votes,
+            mismatched,
+        }
+    }
+}
+
+/// What a `prune` call cleaned up.
+#[near(serializers = [json])]
+pub struct PruneReport {
+    /// Response bodies whose request no longer exists.
+    pub bodies_removed: u32,
+    /// Requests left behind by a yield callback that never completed; their deposit is refunded.
+    pub requests_removed: u32,
+    /// Contract storage released, in bytes.
+    pub storage_freed: u64,
+}
+
+/// How much of a response body has been uploaded, so a relayer can resume an interrupted upload.
+#[near(serializers = [json])]
+pub struct ResponseProgress {
+    pub length: u64,
+    /// sha256 of the bytes stored so far.
+    pub sha256: Vec<u8>,
+}
+
+/// A relayer's upload of a response body. The bytes are stored in `response_chunks` under one key
+/// per chunk, so storing a chunk costs the same however much of the body is already stored.
+#[derive(BorshDeserialize, BorshSerialize, Clone, Default)]
+struct StoredBody {
+    /// Length of each stored chunk, in body order.
+    chunk_lens: Vec<u64>,
+}
+
+impl StoredBody {
+    fn len(&self) -> u64 {
+        self.chunk_lens.iter().sum()
+    }
+
+    /// Estimated storage of a body of `len` bytes stored in `chunks` chunks.
+    fn storage_size(len: u64, chunks: usize) -> u64 {
+        len + BODY_STORAGE_OVERHEAD + chunks as u64 * CHUNK_STORAGE_OVERHEAD
+    }
+
+    /// Index of the chunk byte `offset` falls in and how many of its bytes come before `offset`.
+    /// The end of the body falls at the start of the chunk after the last.
+    fn locate(&self, offset: u64) -> (usize, u64) {
+        let mut start = 0;
+        for (index, len) in self.chunk_lens.iter().enumerate() {
+            if offset < start + len {
+                return (index, offset - start);
+            }
+            start += len;
+        }
+        (self.chunk_lens.len(), offset - start)
+    }
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+struct ResponseMeta {
+    status_code: Option<u16>,
+    headers: Vec<HttpHeader>,
+    body_hash: Option<Vec<u8>>,
+    extraction: Option<ExtractionOutcome>,
+    /// Encoding of the stored body.
+    body_encoding: ResponseEncoding,
+}
+
+impl ResponseMeta {
+    /// sha256 over everything a relayer reports with the body, compared between quorum votes.
+    fn digest(&self) -> Vec<u8> {
+        env::sha256(&near_sdk::borsh::to_vec(self).expect("response meta serializes"))
+    }
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+enum Outcome {
+    Responded(ResponseMeta),
+    Failed {
+        kind: FetchErrorKind,
+        message: String,
+    },
+    Cancelled,
+    /// The request's deadline passed and `expire` was called before the yield timed out.
+    Expired,
+}
 
 #[derive(BorshDeserialize, BorshSerialize)]
 struct StoredRequest {
     yield_id: CryptoHash,
     url: String,
+    request: HttpRequest,
     caller: AccountId,
     context: Option<Vec<u8>>,
+    options: FetchOptions,
+    /// Attached deposit; `fee` goes to the relayers that fulfil the request, the rest is refunded.
+    deposit: NearToken,
+    fee: NearToken,
+    /// Contract storage used by this request and its response body, paid from `deposit - fee`.
+    storage_bytes: u64,
+    /// Relayer submissions so far; quorum requests collect several before resolving.
+    votes: Vec<RelayerVote>,
+    /// Set once a relayer resolves the request; the yield callback reads it.
+    outcome: Option<Outcome>,
+    created_at_height: u64,
+    created_at_ms: u64,
+    /// Last block height at which relayers may respond.
+    deadline_height: u64,
+}
+
+impl StoredRequest {
+    /// Panics unless the deposit, minus the fee, pays for `bytes` of storage.
+    fn require_storage_covered(&self, bytes: u64) {
+        let required = storage_cost(bytes);
+        let available = self.deposit.saturating_sub(self.fee);
+        require!(
+            required <= available,
+            format!(
+                "Attached deposit does not cover storage: {} yoctoNEAR needed, {} available",
+                required.as_yoctonear(),
+                available.as_yoctonear()
+            )
+        );
+    }
+
+    /// Response body bytes the deposit still pays for on top of the storage already used.
+    fn storable_response_bytes(&self) -> u64 {
+        let unused = self
+            .deposit
+            .saturating_sub(self.fee)
+            .saturating_sub(storage_cost(self.storage_bytes));
+        let bytes = unused.as_yoctonear() / env::storage_byte_cost().as_yoctonear();
+        u64::try_from(bytes)
+            .unwrap_or(u64::MAX)
+            .saturating_sub(StoredBody::storage_size(0, CHUNKS_PER_BODY))
+    }
+
+    fn to_pending(&self, request_id: u64) -> PendingRequest {
+        PendingRequest {
+            request_id,
+            url: self.url.clone(),
+            method: self.request.method,
+            headers: self.request.headers.clone(),
+            body: self.request.body.clone(),
+            caller: self.caller.clone(),
+            context: self.context.clone(),
+            yield_id: self.yield_id.to_vec(),
+            quorum: self.options.quorum,
+            extract: self.options.extract.clone(),
+            max_response_bytes: self.options.max_response_bytes,
+            response_encoding: self.options.response_encoding,
+            voters: self.votes.iter().map(|vote| vote.relayer.clone()).collect(),
+            storable_response_bytes: self.storable_response_bytes(),
+            created_at_height: self.created_at_height,
+            created_at_ms: self.created_at_ms,
+            deadline_height: self.deadline_height,
+        }
+    }
 }
 
 #[near(serializers = [json])]
@@ -22,16 +478,74 @@ struct StoredRequest {
 pub struct PendingRequest {
     pub request_id: u64,
     pub url: String,
+    pub method: HttpMethod,
+    pub headers: Vec<HttpHeader>,
+    pub body: Option<Vec<u8>>,
     pub caller: AccountId,
     pub context: Option<Vec<u8>>,
     pub yield_id: Vec<u8>,
+    pub quorum: Option<u8>,
+    pub extract: Option<Vec<String>>,
+    pub max_response_bytes: Option<u64>,
+    pub response_encoding: Option<ResponseEncoding>,
+    /// Relayers that already submitted a response for this quorum request.
+    pub voters: Vec<AccountId>,
+    /// Largest response body the request's deposit still pays storage for, on top of what is
+    /// already stored. Relayers fail larger responses with `InsufficientDeposit` instead of
+    /// uploading them, counting their own partial upload as replaceable.
+    pub storable_response_bytes: u64,
+    pub created_at_height: u64,
+    /// Block timestamp of the `fetch` call in milliseconds.
+    pub created_at_ms: u64,
+    /// Last block height at which a response is accepted.
+    pub deadline_height: u64,
+}
+
+/// One page of `list_requests`.
+#[near(serializers = [json])]
+pub struct RequestPage {
+    pub requests: Vec<PendingRequest>,
+    /// Id of the last request read when the page stopped at `limit`; pass it plus one as
+    /// `min_request_id` to continue. `None` once the scan reached the last stored request.
+    pub last_scanned_id: Option<u64>,
+}
+
+/// Relayer-side failure classes reported through `respond_error`.
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum FetchErrorKind {
+    /// The request could not be built (malformed URL, header or method).
+    InvalidRequest,
+    /// DNS resolution, TLS handshake or TCP connection failed.
+    Connection,
+    /// The upstream server did not answer in time.
+    Timeout,
+    /// Too many or invalid redirects.
+    Redirect,
+    /// The response body could not be read.
+    Body,
+    /// Relayers of a quorum request disagreed and the quorum can no longer be reached.
+    NoConsensus,
+    Other,
+    /// The relayer's URL policy refused the URL (private address, denied host or scheme, ...).
+    PolicyRejected,
+    /// The upstream response exceeded the request's or the relayer's size limit.
+    ResponseTooLarge,
+    /// The request's deposit can't pay for storing the response body.
+    InsufficientDeposit,
 }
 
 #[near(serializers = [json])]
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 pub enum FetchStatus {
     Completed,
     TimedOut,
+    Failed {
+        kind: FetchErrorKind,
+        message: String,
+    },
+    /// The caller withdrew the request with `cancel`.
+    Cancelled,
 }
 
 #[near(serializers = [json])]
@@ -40,9 +554,21 @@ pub struct FetchResult {
     pub request_id: u64,
     pub url: String,
     pub status: FetchStatus,
+    /// HTTP status code reported by the relayer, absent if the fetch timed out.
+    pub status_code: Option<u16>,
+    /// Subset of response headers forwarded by the relayer (content-type, etag, date, ...).
+    pub headers: Vec<HttpHeader>,
     pub body: Option<Vec<u8>>,
+    /// sha256 of the body, verified on chain when the relayer responded.
+    pub body_hash: Option<Vec<u8>>,
+    pub body_encoding: ResponseEncoding,
+    /// `FetchOptions::extract` of the request, and how the relayer applied it.
+    pub extract: Option<Vec<String>>,
+    pub extraction: Option<ExtractionOutcome>,
     pub context: Option<Vec<u8>>,
     pub caller: AccountId,
+    /// Present for quorum requests.
+    pub consensus: Option<ConsensusReport>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -53,51 +579,648 @@ struct FetchCallbackArgs {
 
 #[derive(BorshSerialize, BorshDeserialize, BorshStorageKey)]
 enum StorageKey {
+    /// Requests of the single-relayer layout. Only `migrate` uses it.
     Requests,
+    /// Bodies of the single-relayer layout, keyed by request id alone. Only `migrate` uses it.
     ResponseBodies,
+    Relayers,
+    AccruedFees,
+    RelayerResponseBodies,
+    RequestsById,
+    ResponseChunks,
 }
 
-#[near(contract_state)]
-pub struct Contract {
+/// Request layout of the single-relayer contract, read once by `migrate`.
+#[derive(BorshDeserialize, BorshSerialize)]
+struct LegacyStoredRequest {
+    yield_id: CryptoHash,
+    url: String,
+    caller: AccountId,
+    context: Option<Vec<u8>>,
+}
+
+/// State layout of the single-relayer contract, read once by `migrate`.
+#[derive(BorshDeserialize)]
+struct LegacyContract {
     trusted_relayer: AccountId,
     next_request_id: u64,
-    requests: IterableMap<u64, StoredRequest>,
+    requests: IterableMap<u64, LegacyStoredRequest>,
     response_bodies: IterableMap<u64, Vec<u8>>,
 }
 
+#[near(contract_state)]
+pub struct Contract {
+    owner: AccountId,
+    relayers: IterableSet<AccountId>,
+    fee_schedule: FeeSchedule,
+    url_policy: UrlPolicy,
+    accrued_fees: LookupMap<AccountId, NearToken>,
+    next_request_id: u64,
+    /// Ordered by id, so `list_requests` can page by key.
+    requests: TreeMap<u64, StoredRequest>,
+    /// Bodies being uploaded, one per request and relayer so relayers of a quorum request don't
+    /// overwrite each other. Only the delivered body is left once a request resolves.
+    response_bodies: IterableMap<(u64, AccountId), StoredBody>,
+    /// Chunks of the bodies in `response_bodies`, keyed by the body's key and the chunk's index.
+    response_chunks: LookupMap<(u64, AccountId, u32), Vec<u8>>,
+}
+
+impl Contract {
+    fn ensure_owner(&self) {
+        require!(
+            env::predecessor_account_id() == self.owner,
+            "Only the owner can call this method"
+        );
+    }
+
+    fn ensure_relayer(&self) {
+        require!(
+            self.relayers.contains(&env::predecessor_account_id()),
+            "Only a registered relayer can respond"
+        );
+    }
+
+    fn ensure_resolvable(&self, request_id: u64, yield_id: &[u8]) {
+        let provided: CryptoHash = yield_id
+            .try_into()
+            .unwrap_or_else(|_| env::panic_str("Invalid yield id"));
+
+        let Some(request) = self.requests.get(&request_id) else {
+            env::panic_str("Unknown request id");
+        };
+
+        require!(
+            request.yield_id == provided,
+            "Yield id does not match stored request"
+        );
+        require!(request.outcome.is_none(), "Request already resolved");
+        require!(
+            env::block_height() <= request.deadline_height,
+            "Request deadline has passed"
+        );
+    }
+
+    /// Resolve the request and drop the bodies that won't be delivered, keeping only that of the
+    /// relayer whose response resolved it.
+    fn resolve(&mut self, request_id: u64, outcome: Outcome) {
+        let responder = matches!(outcome, Outcome::Responded(_)).then(env::predecessor_account_id);
+        self.remove_bodies(request_id, responder.as_ref());
+        let request = self
+            .requests
+            .get_mut(&request_id)
+            .unwrap_or_else(|| env::panic_str("Unknown request id"));
+        request.outcome = Some(outcome);
+        require!(
+            env::promise_yield_resume(&request.yield_id, &[]),
+            "Request yield has already timed out"
+        );
+    }
+
+    /// Remove the bodies uploaded for `request_id` by registered relayers and by relayers that
+    /// voted, except `keep`'s. `prune` collects those of relayers that left in between.
+    fn remove_bodies(&mut self, request_id: u64, keep: Option<&AccountId>) {
+        let voters = self
+            .requests
+            .get(&request_id)
+            .map(|request| request.votes.iter().map(|vote| vote.relayer.clone()))
+            .into_iter()
+            .flatten();
+        let uploaders: Vec<AccountId> = self.relayers.iter().cloned().chain(voters).collect();
+        for uploader in uploaders {
+            if Some(&uploader) != keep {
+                self.remove_body(&(request_id, uploader));
+            }
+        }
+    }
+
+    fn is_quorum_request(&self, request_id: u64) -> bool {
+        self.requests
+            .get(&request_id)
+            .is_some_and(|request| request.options.quorum.is_some())
+    }
+
+    /// Record the caller's submission. Plain requests resolve with `outcome` right away; quorum
+    /// requests resolve once `outcome`'s response digest reaches the quorum, or fail once no
+    /// digest can reach it anymore: with the reported error if every relayer failed the same way,
+    /// with `NoConsensus` otherwise.
+    fn record_vote(&mut self, request_id: u64, outcome: Outcome) {
+        let relayer = env::predecessor_account_id();
+        let registered = self.relayers.len() as usize;
+        let request = self
+            .requests
+            .get_mut(&request_id)
+            .unwrap_or_else(|| env::panic_str("Unknown request id"));
+
+        require!(
+            request.votes.iter().all(|vote| vote.relayer != relayer),
+            "Relayer already responded to this request"
+        );
+        let (body_hash, digest, error_kind) = match &outcome {
+            Outcome::Responded(meta) => (meta.body_hash.clone(), Some(meta.digest()), None),
+            Outcome::Failed { kind, .. } => (None, None, Some(*kind)),
+            _ => (None, None, None),
+        };
+        request.votes.push(RelayerVote {
+            relayer,
+            body_hash,
+            response_digest: digest.clone(),
+            error_kind,
+        });
+
+        let Some(quorum) = request.options.quorum.map(usize::from) else {
+            self.resolve(request_id, outcome);
+            return;
+        };
+
+        let agreeing = |digest: &Vec<u8>| {
+            request
+                .votes
+                .iter()
+                .filter(|vote| vote.response_digest.as_ref() == Some(digest))
+                .count()
+        };
+
+        if digest.as_ref().map_or(0, agreeing) >= quorum {
+            self.resolve(request_id, outcome);
+            return;
+        }
+
+        let best = request
+            .votes
+            .iter()
+            .filter_map(|vote| vote.response_digest.as_ref())
+            .map(agreeing)
+            .max()
+            .unwrap_or(0);
+        let outstanding = registered.saturating_sub(request.votes.len());
+        if best + outstanding < quorum {
+            if error_kind.is_some()
+                && request
+                    .votes
+                    .iter()
+                    .all(|vote| vote.error_kind == error_kind)
+            {
+                self.resolve(request_id, outcome);
+                return;
+            }
+            let message = format!(
+                "{} of {} responding relayers agreed, quorum is {}",
+                best,
+                request.votes.len(),
+                quorum
+            );
+            self.resolve(
+                request_id,
+                Outcome::Failed {
+                    kind: FetchErrorKind::NoConsensus,
+                    message,
+                },
+            );
+        }
+    }
+}
+
 impl Contract {
-    fn ensure_trusted(&self) {
+    /// Write `data` at byte `offset` of the calling relayer's body of `request_id`, dropping
+    /// anything stored past it, and charge the storage delta to the request's deposit. Only the
+    /// chunk `offset` falls in is rewritten; the chunks before it stay as they are.
+    fn write_body(&mut self, request_id: u64, data: Vec<u8>, offset: u64) {
+        let Some(request) = self.requests.get(&request_id) else {
+            env::panic_str("Unknown request id");
+        };
+        let key = (request_id, env::predecessor_account_id());
+        let stored = self.response_bodies.get(&key);
+        let stored_size = stored.map_or(0, |stored| {
+            StoredBody::storage_size(stored.len(), stored.chunk_lens.len())
+        });
+        let mut body = stored.cloned().unwrap_or_default();
+        require!(
+            offset <= body.len(),
+            "Chunk offset is past the end of the stored body"
+        );
+        let len = offset + data.len() as u64;
         require!(
-            env::predecessor_account_id() == self.trusted_relayer,
-            "Only the trusted relayer can respond"
+            len <= request.options.body_limit(),
+            "Response body exceeds the maximum size"
         );
+
+        let (index, kept) = body.locate(offset);
+        let chunk = if kept == 0 {
+            data
+        } else {
+            let mut chunk = self.chunk(&key, index)[..kept as usize].to_vec();
+            chunk.extend_from_slice(&data);
+            chunk
+        };
+        let chunks = index + usize::from(!chunk.is_empty());
+        // Reject a body the deposit can't pay for before writing it.
+        let growth = StoredBody::storage_size(len, chunks).saturating_sub(stored_size);
+        request.require_storage_covered(request.storage_bytes + growth);
+
+        let before = env::storage_usage();
+        for dropped in chunks..body.chunk_lens.len() {
+            self.response_chunks.set(chunk_key(&key, dropped), None);
+        }
+        body.chunk_lens.truncate(index);
+        if !chunk.is_empty() {
+            body.chunk_lens.push(chunk.len() as u64);
+            self.response_chunks.insert(chunk_key(&key, index), chunk);
+        }
+        self.response_bodies.insert(key, body);
+        self.response_bodies.flush();
+        self.response_chunks.flush();
+        self.charge_storage(request_id, before);
+    }
+
+    fn chunk(&self, key: &(u64, AccountId), index: usize) -> &[u8] {
+        self.response_chunks
+            .get(&chunk_key(key, index))
+            .unwrap_or_else(|| env::panic_str("Missing response chunk"))
+    }
+
+    /// The body stored under `key`, assembled from its chunks.
+    fn read_body(&self, key: &(u64, AccountId)) -> Option<Vec<u8>> {
+        let body = self.response_bodies.get(key)?;
+        let mut bytes = Vec::with_capacity(body.len() as usize);
+        for index in 0..body.chunk_lens.len() {
+            bytes.extend_from_slice(self.chunk(key, index));
+        }
+        Some(bytes)
+    }
+
+    /// Remove the body stored under `key` together with its chunks.
+    fn remove_body(&mut self, key: &(u64, AccountId)) {
+        if let Some(body) = self.response_bodies.remove(key) {
+            for index in 0..body.chunk_lens.len() {
+                self.response_chunks.set(chunk_key(key, index), None);
+            }
+        }
+    }
+
+    /// Add the storage used since `before` to the request and make sure its deposit covers it.
+    fn charge_storage(&mut self, request_id: u64, before: u64) {
+        let after = env::storage_usage();
+        let request = self
+            .requests
+            .get_mut(&request_id)
+            .unwrap_or_else(|| env::panic_str("Unknown request id"));
+        request.storage_bytes = (request.storage_bytes + after).saturating_sub(before);
+        request.require_storage_covered(request.storage_bytes);
     }
+
+    fn credit_fee(&mut self, account_id: &AccountId, amount: NearToken) {
+        if amount.is_zero() {
+            return;
+        }
+        let balance = self
+            .accrued_fees
+            .get(account_id)
+            .copied()
+            .unwrap_or(NearToken::from_yoctonear(0));
+        self.accrued_fees
+            .insert(account_id.clone(), balance.saturating_add(amount));
+    }
+
+    /// Split the request fee between `earners` and refund the rest of the deposit to the caller.
+    /// Without earners (timeout, failure, no consensus) the whole deposit is refunded.
+    fn settle_fees(&mut self, request: &StoredRequest, earners: &[AccountId]) {
+        let fee = if earners.is_empty() {
+            NearToken::from_yoctonear(0)
+        } else {
+            request.fee
+        };
+
+        if let Some((first, rest)) = earners.split_first() {
+            let count = earners.len() as u128;
+            let share = fee.as_yoctonear() / count;
+            let remainder = fee.as_yoctonear() - share * count;
+            self.credit_fee(first, NearToken::from_yoctonear(share + remainder));
+            for earner in rest {
+                self.credit_fee(earner, NearToken::from_yoctonear(share));
+            }
+        }
+
+        let refund = request.deposit.saturating_sub(fee);
+        if !refund.is_zero() {
+            let _ = Promise::new(request.caller.clone()).transfer(refund);
+        }
+    }
+}
+
+fn emit_event(event: &str, data: serde_json::Value) {
+    let event = serde_json::json!({
+        "standard": EVENT_STANDARD,
+        "version": EVENT_VERSION,
+        "event": event,
+        "data": [data]
+    });
+    env::log_str(&format!("EVENT_JSON:{}", event));
 }
 
 impl Default for Contract {
     fn default() -> Self {
-        env::panic_str("Contract must be initialized with new(trusted_relayer)");
+        env::panic_str("Contract must be initialized with new(trusted_relayer, owner)");
     }
 }
 
 #[near]
 impl Contract {
+    /// `trusted_relayer` seeds the relayer registry; `owner` defaults to the account calling `new`.
     #[init]
-    pub fn new(trusted_relayer: AccountId) -> Self {
+    pub fn new(trusted_relayer: AccountId, owner: Option<AccountId>) -> Self {
         require!(!env::state_exists(), "Already initialized");
+        let mut relayers = IterableSet::new(StorageKey::Relayers);
+        relayers.insert(trusted_relayer);
         Self {
-            trusted_relayer,
+            owner: owner.unwrap_or_else(env::predecessor_account_id),
+            relayers,
+            fee_schedule: FeeSchedule::default(),
+            url_policy: UrlPolicy::default(),
+            accrued_fees: LookupMap::new(StorageKey::AccruedFees),
             next_request_id: 0,
-            requests: IterableMap::new(StorageKey::Requests),
-            response_bodies: IterableMap::new(StorageKey::ResponseBodies),
+            requests: TreeMap::new(StorageKey::RequestsById),
+            response_bodies: IterableMap::new(StorageKey::RelayerResponseBodies),
+            response_chunks: LookupMap::new(StorageKey::ResponseChunks),
+        }
+    }
+
+    /// Upgrade the state of a contract deployed before the relayer registry. Its
+    /// `trusted_relayer` seeds the registry and `owner` defaults to the contract account. Requests
+    /// in flight carry no deposit to pay for a response, so they are migrated with their deadline
+    /// already passed and their partial uploads dropped: anyone can `expire` them, or they time
+    /// out with the yield.
+    #[private]
+    #[init(ignore_state)]
+    pub fn migrate(owner: Option<AccountId>) -> Self {
+        let state =
+            env::storage_read(b"STATE").unwrap_or_else(|| env::panic_str("No state to migrate"));
+        // Deploys call `migrate` whenever state exists, so state already in the current layout is
+        // kept as it is.
+        if let Ok(current) = Self::try_from_slice(&state) {
+            return current;
+        }
+        let mut legacy = LegacyContract::try_from_slice(&state)
+            .unwrap_or_else(|_| env::panic_str("State is not in the single-relayer layout"));
+        let legacy_requests: Vec<(u64, LegacyStoredRequest)> = legacy.requests.drain().collect();
+        legacy.requests.flush();
+        // Partial uploads of the migrated requests can't be answered anymore.
+        legacy.response_bodies.clear();
+        legacy.response_bodies.flush();
+
+        let mut relayers = IterableSet::new(StorageKey::Relayers);
+        relayers.insert(legacy.trusted_relayer);
+        let mut requests = TreeMap::new(StorageKey::RequestsById);
+        let height = env::block_height();
+        for (request_id, legacy_request) in legacy_requests {
+            requests.insert(
+                request_id,
+                StoredRequest {
+                    yield_id: legacy_request.yield_id,
+                    url: legacy_request.url,
+                    request: HttpRequest::default(),
+                    caller: legacy_request.caller,
+                    context: legacy_request.context,
+                    options: FetchOptions::default(),
+                    deposit: NearToken::from_yoctonear(0),
+                    fee: NearToken::from_yoctonear(0),
+                    storage_bytes: 0,
+                    votes: Vec::new(),
+                    outcome: None,
+                    created_at_height: height,
+                    created_at_ms: env::block_timestamp_ms(),
+                    deadline_height: height,
+                },
+            );
+        }
+
+        Self {
+            owner: owner.unwrap_or_else(env::current_account_id),
+            relayers,
+            fee_schedule: FeeSchedule::default(),
+            url_policy: UrlPolicy::default(),
+            accrued_fees: LookupMap::new(StorageKey::AccruedFees),
+            next_request_id: legacy.next_request_id,
+            requests,
+            response_bodies: IterableMap::new(StorageKey::RelayerResponseBodies),
+            response_chunks: LookupMap::new(StorageKey::ResponseChunks),
         }
     }
 
+    pub fn owner(&self) -> AccountId {
+        self.owner.clone()
+    }
+
+    pub fn set_owner(&mut self, new_owner: AccountId) {
+        self.ensure_owner();
+        let previous = std::mem::replace(&mut self.owner, new_owner.clone());
+        emit_event(
+            "owner_changed",
+            serde_json::json!({ "previous_owner": previous, "new_owner": new_owner }),
+        );
+    }
+
+    pub fn add_relayer(&mut self, account_id: AccountId) {
+        self.ensure_owner();
+        require!(
+            self.relayers.insert(account_id.clone()),
+            "Relayer already registered"
+        );
+        emit_event(
+            "relayer_added",
+            serde_json::json!({ "account_id": account_id }),
+        );
+    }
+
+    pub fn remove_relayer(&mut self, account_id: AccountId) {
+        self.ensure_owner();
+        require!(self.relayers.remove(&account_id), "Relayer not registered");
+        emit_event(
+            "relayer_removed",
+            serde_json::json!({ "account_id": account_id }),
+        );
+    }
+
+    pub fn list_relayers(&self) -> Vec<AccountId> {
+        self.relayers.iter().cloned().collect()
+    }
+
+    pub fn is_relayer(&self, account_id: AccountId) -> bool {
+        self.relayers.contains(&account_id)
+    }
+
+    /// The first registered relayer, for clients of the single-relayer contract. Prefer
+    /// `list_relayers`.
     pub fn trusted_relayer(&self) -> AccountId {
-        self.trusted_relayer.clone()
+        self.relayers
+            .iter()
+            .next()
+            .cloned()
+            .unwrap_or_else(|| env::panic_str("No relayer is registered"))
+    }
+
+    pub fn get_fee_schedule(&self) -> FeeSchedule {
+        self.fee_schedule.clone()
+    }
+
+    pub fn set_fee_schedule(&mut self, fee_schedule: FeeSchedule) {
+        self.ensure_owner();
+        emit_event(
+            "fee_schedule_updated",
+            serde_json::json!({
+                "base": fee_schedule.base,
+                "per_kilobyte": fee_schedule.per_kilobyte,
+            }),
+        );
+        self.fee_schedule = fee_schedule;
+    }
+
+    pub fn get_url_policy(&self) -> UrlPolicy {
+        self.url_policy.clone()
     }
 
-    pub fn fetch(&mut self, url: String, context: Option<Vec<u8>>) {
+    /// Replace the URL policy. Schemes and domains are compared case-insensitively.
+    pub fn set_url_policy(&mut self, url_policy: UrlPolicy) {
+        self.ensure_owner();
+        require!(
+            url_policy.max_url_length > 0,
+            "max_url_length must be positive"
+        );
+        let url_policy = url_policy.normalized();
+        require!(
+            !url_policy.allowed_schemes.is_empty(),
+            "At least one URL scheme must be allowed"
+        );
+        emit_event(
+            "url_policy_updated",
+            serde_json::json!({
+                "allowed_schemes": url_policy.allowed_schemes,
+                "allowed_domains": url_policy.allowed_domains,
+                "max_url_length": url_policy.max_url_length,
+            }),
+        );
+        self.url_policy = url_policy;
+    }
+
+    /// Deposit `fetch` needs for the given request: the fee plus storage for the request and a
+    /// response of up to `expected_response_bytes`, uploaded by each of the `quorum` relayers of a
+    /// quorum request. Unused storage is refunded on completion.
+    pub fn estimate_deposit(
+        &self,
+        url: String,
+        context: Option<Vec<u8>>,
+        request: Option<HttpRequest>,
+        expected_response_bytes: Option<u64>,
+        quorum: Option<u8>,
+    ) -> NearToken {
+        let request = request.unwrap_or_default();
+        let request_bytes = request_size(&url, &request, context.as_ref());
+        let response_bytes = expected_response_bytes.unwrap_or(0).min(MAX_RESPONSE_BYTES);
+        let uploads = u64::from(quorum.unwrap_or(1).max(1));
+        let storage = storage_cost(
+            request_bytes
+                + REQUEST_STORAGE_OVERHEAD
+                + uploads * StoredBody::storage_size(response_bytes, CHUNKS_PER_BODY),
+        );
+        self.fee_schedule
+            .fee_for(request_bytes)
+            .saturating_add(storage)
+    }
+
+    pub fn max_response_bytes(&self) -> u64 {
+        MAX_RESPONSE_BYTES
+    }
+
+    pub fn get_accrued_fees(&self, account_id: AccountId) -> NearToken {
+        self.accrued_fees
+            .get(&account_id)
+            .copied()
+            .unwrap_or(NearToken::from_yoctonear(0))
+    }
+
+    /// Transfer the caller's accrued relayer fees to it.
+    pub fn withdraw_fees(&mut self) -> Promise {
+        let account_id = env::predecessor_account_id();
+        let amount = self
+            .accrued_fees
+            .remove(&account_id)
+            .filter(|amount| !amount.is_zero())
+            .unwrap_or_else(|| env::panic_str("No fees to withdraw"));
+
+        Promise::new(account_id.clone()).transfer(amount).then(
+            Self::ext(env::current_account_id())
+                .with_static_gas(WITHDRAW_CALLBACK_GAS)
+                .on_fees_withdrawn(account_id, amount),
+        )
+    }
+
+    #[private]
+    pub fn on_fees_withdrawn(&mut self, account_id: AccountId, amount: NearToken) {
+        match env::promise_result(0) {
+            PromiseResult::Successful(_) => emit_event(
+                "fees_withdrawn",
+                serde_json::json!({ "account_id": account_id, "amount": amount }),
+            ),
+            // Transfer failed; restore the balance so it can be withdrawn later.
+            PromiseResult::Failed => self.credit_fee(&account_id, amount),
+        }
+    }
+
+    #[payable]
+    pub fn fetch(
+        &mut self,
+        url: String,
+        context: Option<Vec<u8>>,
+        request: Option<HttpRequest>,
+        options: Option<FetchOptions>,
+    ) {
+        self.url_policy.validate(&url);
+        let request = request.unwrap_or_default();
+        require!(
+            request.body.is_none() || !matches!(request.method, HttpMethod::Get | HttpMethod::Head),
+            "GET and HEAD requests cannot carry a body"
+        );
+        let options = options.unwrap_or_default();
+        if let Some(quorum) = options.quorum {
+            require!(
+                quorum >= 1 && u32::from(quorum) <= self.relayers.len(),
+                "Quorum must be between 1 and the number of registered relayers"
+            );
+        }
+        if let Some(pointers) = &options.extract {
+            require!(
+                !pointers.is_empty() && pointers.len() <= MAX_EXTRACT_POINTERS,
+                format!("extract must list between 1 and {MAX_EXTRACT_POINTERS} JSON Pointers")
+            );
+            require!(
+                pointers
+                    .iter()
+                    .all(|pointer| pointer.len() <= MAX_EXTRACT_POINTER_LEN
+                        && (pointer.is_empty() || pointer.starts_with('/'))),
+                "extract entries must be JSON Pointers such as /main/temp"
+            );
+        }
+        require!(
+            options.max_response_bytes != Some(0),
+            "max_response_bytes must be positive"
+        );
+        let timeout_blocks = options.timeout_blocks.unwrap_or(YIELD_TIMEOUT_BLOCKS);
+        require!(
+            timeout_blocks >= 1 && timeout_blocks <= YIELD_TIMEOUT_BLOCKS,
+            format!("timeout_blocks must be between 1 and {YIELD_TIMEOUT_BLOCKS}")
+        );
+
+        let deposit = env::attached_deposit();
+        let fee = self
+            .fee_schedule
+            .fee_for(request_size(&url, &request, context.as_ref()));
+        require!(
+            deposit >= fee,
+            format!(
+                "Attach at least {} yoctoNEAR to cover the request fee",
+                fee.as_yoctonear()
+            )
+        );
+
         let caller = env::predecessor_account_id();
         let request_id = self.next_request_id;
         self.next_request_id = self
@@ -119,80 +1242,343 @@ impl Contract {
             .try_into()
             .expect("Invalid yield id");
 
+        let method = request.method;
+        let quorum = options.quorum;
+        let created_at_height = env::block_height();
+        let deadline_height = created_at_height + timeout_blocks;
         let stored = StoredRequest {
             yield_id,
             url: url.clone(),
+            request,
             caller: caller.clone(),
             context: context.clone(),
+            options,
+            deposit,
+            fee,
+            storage_bytes: 0,
+            votes: Vec::new(),
+            outcome: None,
+            created_at_height,
+            created_at_ms: env::block_timestamp_ms(),
+            deadline_height,
         };
+        let storage_before = env::storage_usage();
         self.requests.insert(request_id, stored);
+        self.requests.flush();
+        self.charge_storage(request_id, storage_before);
 
-        let event = serde_json::json!({
-            "standard": "http_fetch",
-            "version": "1.0.0",
-            "event": "fetch_request",
-            "data": [{
+        emit_event(
+            "fetch_request",
+            serde_json::json!({
                 "request_id": request_id,
                 "url": url,
+                "method": method,
                 "caller": caller,
-            }]
-        });
-        env::log_str(&format!("EVENT_JSON:{}", event));
+                "quorum": quorum,
+                "deadline_height": deadline_height,
+            }),
+        );
 
         env::promise_return(promise_id);
     }
 
-    pub fn list_requests(&self) -> Vec<PendingRequest> {
+    /// Page through unresolved requests in id order, starting at `min_request_id` and optionally
+    /// only those from `caller`. `limit` bounds how many stored requests one call reads, resolved
+    /// and filtered-out ones included, so a page may hold fewer requests while more follow; keep
+    /// paging from `last_scanned_id` until it is `None`. `limit` defaults to `DEFAULT_LIST_LIMIT`
+    /// and is capped at `MAX_LIST_LIMIT`.
+    pub fn list_requests(
+        &self,
+        limit: Option<u32>,
+        caller: Option<AccountId>,
+        min_request_id: Option<u64>,
+    ) -> RequestPage {
+        let limit = limit.unwrap_or(DEFAULT_LIST_LIMIT).min(MAX_LIST_LIMIT) as usize;
+        let mut requests = Vec::new();
+        let mut scanned = 0;
+        let mut last_scanned_id = None;
+        for (request_id, req) in self
+            .requests
+            .range(min_request_id.unwrap_or(0)..)
+            .take(limit)
+        {
+            scanned += 1;
+            last_scanned_id = Some(*request_id);
+            if req.outcome.is_none() && caller.as_ref().is_none_or(|caller| &req.caller == caller) {
+                requests.push(req.to_pending(*request_id));
+            }
+        }
+        RequestPage {
+            requests,
+            last_scanned_id: last_scanned_id.filter(|_| scanned == limit),
+        }
+    }
+
+    pub fn get_request(&self, request_id: u64) -> Option<PendingRequest> {
         self.requests
-            .iter()
-            .map(|(request_id, req)| PendingRequest {
-                request_id: *request_id,
-                url: req.url.clone(),
-                caller: req.caller.clone(),
-                context: req.context.clone(),
-                yield_id: req.yield_id.to_vec(),
-            })
-            .collect()
+            .get(&request_id)
+            .filter(|req| req.outcome.is_none())
+            .map(|req| req.to_pending(request_id))
     }
 
-    pub fn respond(&mut self, request_id: u64, yield_id: Vec<u8>, body: Option<Vec<u8>>) {
-        self.ensure_trusted();
+    pub fn respond(
+        &mut self,
+        request_id: u64,
+        yield_id: Vec<u8>,
+        body: Option<Vec<u8>>,
+        status_code: Option<u16>,
+        headers: Option<Vec<HttpHeader>>,
+        body_hash: Option<Vec<u8>>,
+        body_len: Option<u64>,
+        extraction: Option<ExtractionOutcome>,
+        body_encoding: Option<ResponseEncoding>,
+    ) {
+        self.ensure_relayer();
+        self.ensure_resolvable(request_id, &yield_id);
+        if self.is_quorum_request(request_id) {
+            require!(body_hash.is_some(), "Quorum requests require a body_hash");
+        }
+        let options = &self
+            .requests
+            .get(&request_id)
+            .expect("ensure_resolvable checked the request")
+            .options;
+        require!(
+            extraction.is_some() == options.extract.is_some(),
+            "extraction must be reported exactly for requests with extract"
+        );
+        let body_encoding = body_encoding.unwrap_or_default();
+        require!(
+            body_encoding == ResponseEncoding::Identity
+                || options.response_encoding == Some(body_encoding),
+            "Body encoding was not allowed by the request"
+        );
 
-        let provided: CryptoHash = yield_id
-            .as_slice()
-            .try_into()
-            .unwrap_or_else(|_| env::panic_str("Invalid yield id"));
+        if let Some(data) = body {
+            self.write_body(request_id, data, 0);
+        }
+        let Some(stored) = self.read_body(&(request_id, env::predecessor_account_id())) else {
+            env::panic_str("No stored body for request");
+        };
+        if let Some(expected) = body_len {
+            require!(
+                stored.len() as u64 == expected,
+                "Stored body does not match body_len"
+            );
+        }
+        let stored_hash = env::sha256(&stored);
+        if let Some(expected) = &body_hash {
+            require!(
+                &stored_hash == expected,
+                "Stored body does not match body_hash"
+            );
+        }
+
+        let outcome = Outcome::Responded(ResponseMeta {
+            status_code,
+            headers: headers.unwrap_or_default(),
+            body_hash: Some(stored_hash.clone()),
+            extraction,
+            body_encoding,
+        });
+        self.record_vote(request_id, outcome);
+    }
 
+    /// `respond` with borsh-serialized args, so an inline `body` costs a byte per byte instead
+    /// of up to four.
+    pub fn respond_borsh(
+        &mut self,
+        #[serializer(borsh)] request_id: u64,
+        #[serializer(borsh)] yield_id: Vec<u8>,
+        #[serializer(borsh)] body: Option<Vec<u8>>,
+        #[serializer(borsh)] status_code: Option<u16>,
+        #[serializer(borsh)] headers: Option<Vec<HttpHeader>>,
+        #[serializer(borsh)] body_hash: Option<Vec<u8>>,
+        #[serializer(borsh)] body_len: Option<u64>,
+        #[serializer(borsh)] extraction: Option<ExtractionOutcome>,
+        #[serializer(borsh)] body_encoding: Option<ResponseEncoding>,
+    ) {
+        self.respond(
+            request_id,
+            yield_id,
+            body,
+            status_code,
+            headers,
+            body_hash,
+            body_len,
+            extraction,
+            body_encoding,
+        );
+    }
+
+    /// Resume the request with a relayer-side failure instead of waiting for the yield to time out.
+    pub fn respond_error(
+        &mut self,
+        request_id: u64,
+        yield_id: Vec<u8>,
+        error_kind: FetchErrorKind,
+        message: String,
+    ) {
+        self.ensure_relayer();
+        self.ensure_resolvable(request_id, &yield_id);
+        require!(
+            message.len() <= MAX_ERROR_MESSAGE_LEN,
+            "Error message is too long"
+        );
+
+        // Only this relayer's upload is dropped; other relayers may still complete a quorum.
+        self.remove_body(&(request_id, env::predecessor_account_id()));
+        let outcome = Outcome::Failed {
+            kind: error_kind,
+            message,
+        };
+        self.record_vote(request_id, outcome);
+    }
+
+    /// Withdraw a queued request. Only the original caller may cancel; its callback receives a
+    /// `Cancelled` status and the whole deposit is refunded.
+    pub fn cancel(&mut self, request_id: u64) {
         let Some(request) = self.requests.get(&request_id) else {
             env::panic_str("Unknown request id");
         };
+        let caller = env::predecessor_account_id();
+        require!(
+            request.caller == caller,
+            "Only the original caller can cancel a request"
+        );
+        require!(request.outcome.is_none(), "Request already resolved");
+
+        self.resolve(request_id, Outcome::Cancelled);
+        emit_event(
+            "fetch_cancelled",
+            serde_json::json!({ "request_id": request_id, "caller": caller }),
+        );
+    }
 
+    /// Resolve a request whose deadline has passed with a `TimedOut` status, instead of waiting
+    /// for the protocol to time out the yield. Callable by anyone; the deposit is refunded.
+    pub fn expire(&mut self, request_id: u64) {
+        let Some(request) = self.requests.get(&request_id) else {
+            env::panic_str("Unknown request id");
+        };
+        require!(request.outcome.is_none(), "Request already resolved");
         require!(
-            request.yield_id == provided,
-            "Yield id does not match stored request"
+            env::block_height() > request.deadline_height,
+            "Request deadline has not passed yet"
         );
 
-        if let Some(data) = body {
-            self.response_bodies.insert(request_id, data);
-        } else if self.response_bodies.get(&request_id).is_none() {
-            env::panic_str("No stored body for request");
-        }
+        self.resolve(request_id, Outcome::Expired);
+        emit_event(
+            "fetch_expired",
+            serde_json::json!({ "request_id": request_id }),
+        );
+    }
+
+    /// Write `data` at byte `offset` of the calling relayer's stored response body, dropping
+    /// anything stored past it. `offset` may not be beyond the stored length, so chunks can't leave
+    /// gaps; writing at 0 starts the body over.
+    pub fn store_response_chunk(&mut self, request_id: u64, data: Vec<u8>, offset: u64) {
+        self.ensure_relayer();
+        let Some(request) = self.requests.get(&request_id) else {
+            env::panic_str("Unknown request id");
+        };
+        require!(request.outcome.is_none(), "Request already resolved");
+        require!(
+            env::block_height() <= request.deadline_height,
+            "Request deadline has passed"
+        );
+        self.write_body(request_id, data, offset);
+    }
 
-        env::promise_yield_resume(&request.yield_id, &[]);
+    /// `store_response_chunk` with `data` as a base64 string, which is about a third of the size
+    /// of the JSON byte array in transaction args.
+    pub fn store_response_chunk_base64(&mut self, request_id: u64, data: Base64VecU8, offset: u64) {
+        self.store_response_chunk(request_id, data.into(), offset);
     }
 
-    pub fn store_response_chunk(&mut self, request_id: u64, data: Vec<u8>, append: bool) {
-        self.ensure_trusted();
-        let mut current = if append {
-            self.response_bodies
-                .get(&request_id)
-                .cloned()
-                .unwrap_or_default()
-        } else {
-            Vec::new()
+    /// `store_response_chunk` with borsh-serialized args, which carry `data` as raw bytes. This
+    /// is what the relayer uses.
+    pub fn store_response_chunk_borsh(
+        &mut self,
+        #[serializer(borsh)] request_id: u64,
+        #[serializer(borsh)] data: Vec<u8>,
+        #[serializer(borsh)] offset: u64,
+    ) {
+        self.store_response_chunk(request_id, data, offset);
+    }
+
+    /// Remove response bodies that no longer belong to a request and requests that outlived their
+    /// yield without the callback cleaning them up, at most `limit` of each per call. Only callable
+    /// by the owner or a registered relayer.
+    pub fn prune(&mut self, limit: Option<u32>) -> PruneReport {
+        let caller = env::predecessor_account_id();
+        require!(
+            caller == self.owner || self.relayers.contains(&caller),
+            "Only the owner or a registered relayer can prune"
+        );
+        let limit = limit.unwrap_or(DEFAULT_LIST_LIMIT).min(MAX_LIST_LIMIT) as usize;
+        let now = env::block_height();
+
+        // Ids grow with the block height, so stale requests are the oldest ones.
+        let stale_requests: Vec<u64> = self
+            .requests
+            .iter()
+            .take_while(|(_, req)| {
+                req.created_at_height + YIELD_TIMEOUT_BLOCKS + STALE_REQUEST_GRACE_BLOCKS < now
+            })
+            .map(|(request_id, _)| *request_id)
+            .take(limit)
+            .collect();
+        let orphaned_bodies: Vec<(u64, AccountId)> = self
+            .response_bodies
+            .keys()
+            .filter(|(request_id, _)| !self.requests.contains_key(request_id))
+            .cloned()
+            .take(limit)
+            .collect();
+
+        let before = env::storage_usage();
+        for request_id in &stale_requests {
+            self.remove_bodies(*request_id, None);
+            if let Some(request) = self.requests.remove(request_id) {
+                self.settle_fees(&request, &[]);
+            }
+        }
+        for key in &orphaned_bodies {
+            self.remove_body(key);
+        }
+        self.requests.flush();
+        self.response_bodies.flush();
+        self.response_chunks.flush();
+
+        let report = PruneReport {
+            bodies_removed: orphaned_bodies.len() as u32,
+            requests_removed: stale_requests.len() as u32,
+            storage_freed: before.saturating_sub(env::storage_usage()),
         };
-        current.extend_from_slice(&data);
-        self.response_bodies.insert(request_id, current);
+        emit_event(
+            "pruned",
+            serde_json::json!({
+                "bodies_removed": report.bodies_removed,
+                "requests_removed": report.requests_removed,
+                "storage_freed": report.storage_freed,
+            }),
+        );
+        report
+    }
+
+    /// Length and hash of the response body `relayer` has stored so far for a pending request.
+    pub fn get_response_progress(
+        &self,
+        request_id: u64,
+        relayer: AccountId,
+    ) -> Option<ResponseProgress> {
+        self.requests.get(&request_id)?;
+        let body = self.read_body(&(request_id, relayer)).unwrap_or_default();
+        Some(ResponseProgress {
+            length: body.len() as u64,
+            sha256: env::sha256(&body),
+        })
     }
 
     #[private]
@@ -202,25 +1588,83 @@ impl Contract {
             .remove(&request_id)
             .unwrap_or_else(|| env::panic_str("Missing request for callback"));
 
-        let stored_body = self.response_bodies.remove(&request_id);
+        // A yield that timed out resumes unsuccessfully, whatever outcome was recorded.
+        let resumed = matches!(env::promise_result(0), PromiseResult::Successful(_));
+        let accepted = match &request.outcome {
+            Some(Outcome::Responded(meta)) if resumed => Some(meta),
+            _ => None,
+        };
+        let accepted_digest = accepted.map(ResponseMeta::digest);
+        // `resolve` left only the delivered body. Partial uploads of a request that timed out
+        // are still there.
+        let mut stored_body = None;
+        let uploaders: Vec<AccountId> = self
+            .relayers
+            .iter()
+            .cloned()
+            .chain(request.votes.iter().map(|vote| vote.relayer.clone()))
+            .collect();
+        for uploader in uploaders {
+            let key = (request_id, uploader);
+            if stored_body.is_none() {
+                stored_body = self.read_body(&key);
+            }
+            self.remove_body(&key);
+        }
 
-        match env::promise_result(0) {
-            PromiseResult::Successful(_) => FetchResult {
-                request_id,
-                url: request.url,
-                status: FetchStatus::Completed,
-                body: stored_body,
-                context: request.context,
-                caller: request.caller,
-            },
-            PromiseResult::Failed => FetchResult {
-                request_id,
-                url: request.url,
-                status: FetchStatus::TimedOut,
-                body: None,
-                context: request.context,
-                caller: request.caller,
-            },
+        // Only a response delivered as `Completed` earns the fee; relayer-side failures are
+        // refunded like timeouts, so failing a request is never cheaper for a relayer than
+        // fetching it.
+        let earners: Vec<AccountId> = match accepted_digest {
+            Some(_) => request
+                .votes
+                .iter()
+                .filter(|vote| vote.response_digest == accepted_digest)
+                .map(|vote| vote.relayer.clone())
+                .collect(),
+            None => Vec::new(),
+        };
+        self.settle_fees(&request, &earners);
+        let consensus = request
+            .options
+            .quorum
+            .map(|quorum| ConsensusReport::new(quorum, accepted, request.votes));
+
+        let (status, meta, body) = match (resumed, request.outcome) {
+            (true, Some(Outcome::Responded(meta))) => {
+                (FetchStatus::Completed, Some(meta), stored_body)
+            }
+            (true, Some(Outcome::Failed { kind, message })) => {
+                (FetchStatus::Failed { kind, message }, None, None)
+            }
+            (true, Some(Outcome::Cancelled)) => (FetchStatus::Cancelled, None, None),
+            // The deadline or the yield expired before any relayer resolved the request.
+            _ => (FetchStatus::TimedOut, None, None),
+        };
+        let (status_code, headers, body_hash, extraction, body_encoding) = match meta {
+            Some(meta) => (
+                meta.status_code,
+                meta.headers,
+                meta.body_hash,
+                meta.extraction,
+                meta.body_encoding,
+            ),
+            None => (None, Vec::new(), None, None, ResponseEncoding::Identity),
+        };
+        FetchResult {
+            request_id,
+            url: request.url,
+            status,
+            status_code,
+            headers,
+            body,
+            body_hash,
+            body_encoding,
+            extract: request.options.extract,
+            extraction,
+            context: request.context,
+            caller: request.caller,
+            consensus,
         }
     }
 }
@@ -0,0 +1,47 @@
+mod common;
+
+use anyhow::Result;
+use near_workspaces::types::NearToken;
+use relayer::policy::UrlPolicy;
+use relayer::provision_access_keys;
+use serde::Deserialize;
+use serde_json::json;
+
+#[derive(Deserialize)]
+struct FetchResultView {
+    status: serde_json::Value,
+}
+
+#[tokio::test]
+async fn relayer_signs_with_provisioned_access_keys() -> Result<()> {
+    let (worker, fetcher, relayer) = common::setup().await?;
+    let relayer_config =
+        common::relayer_config(&worker, &fetcher, &relayer)?.with_url_policy(UrlPolicy::default());
+
+    let keys = provision_access_keys(&relayer_config, 3, None).await?;
+    assert_eq!(keys.len(), 3);
+    let access_keys = relayer.view_access_keys().await?;
+    assert_eq!(access_keys.len(), 4, "full access key plus three pool keys");
+
+    let relayer_config = relayer_config.with_access_keys(&keys)?;
+
+    // The default URL policy refuses loopback addresses, so the relayer answers through respond_error
+    // without connecting.
+    let fetch_tx = fetcher
+        .call("fetch")
+        .args_json(json!({
+            "url": "http://127.0.0.1:9/",
+            "context": null
+        }))
+        .deposit(NearToken::from_millinear(100))
+        .max_gas()
+        .transact_async()
+        .await?;
+
+    common::process_pending(&relayer_config).await?;
+
+    let fetch_result: FetchResultView = fetch_tx.await?.json()?;
+    assert_eq!(fetch_result.status["Failed"]["kind"], "PolicyRejected");
+
+    Ok(())
+}
@@ -0,0 +1,65 @@
+mod common;
+
+use anyhow::Result;
+use near_workspaces::types::NearToken;
+use serde::Deserialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+#[derive(Deserialize)]
+struct PendingRequestView {
+    request_id: u64,
+}
+
+#[derive(Deserialize)]
+struct FetchResultView {
+    status: serde_json::Value,
+    #[serde(default)]
+    body: Option<Vec<u8>>,
+    #[serde(default)]
+    body_hash: Option<Vec<u8>>,
+}
+
+/// A body several chunks long goes through `store_response_chunk_borsh` one chunk per call, and
+/// an upload cut short earlier is resumed from the part already stored.
+#[tokio::test]
+async fn relayer_uploads_large_bodies_in_chunks() -> Result<()> {
+    let (worker, fetcher, relayer) = common::setup().await?;
+
+    let body: Vec<u8> = (0..900_000).map(|i| (i % 251) as u8).collect();
+    let upstream = common::spawn_upstream(body.clone()).await?;
+    let relayer_config = common::relayer_config(&worker, &fetcher, &relayer)?;
+
+    let deposit: NearToken = fetcher
+        .view("estimate_deposit")
+        .args_json(json!({ "url": upstream, "expected_response_bytes": body.len() }))
+        .await?
+        .json()?;
+    let fetch_tx = fetcher
+        .call("fetch")
+        .args_json(json!({ "url": upstream, "context": null }))
+        .deposit(deposit)
+        .max_gas()
+        .transact_async()
+        .await?;
+
+    let pending: PendingRequestView = common::wait_for_pending(&fetcher).await?;
+
+    // Pretend an earlier attempt stored the start of the body before it gave up.
+    relayer
+        .call(fetcher.id(), "store_response_chunk_borsh")
+        .args_borsh((pending.request_id, body[..100_000].to_vec(), 0u64))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+
+    common::process_pending(&relayer_config).await?;
+
+    let fetch_result: FetchResultView = fetch_tx.await?.json()?;
+    assert_eq!(fetch_result.status, json!("Completed"));
+    assert_eq!(fetch_result.body_hash, Some(Sha256::digest(&body).to_vec()));
+    assert_eq!(fetch_result.body, Some(body));
+
+    Ok(())
+}
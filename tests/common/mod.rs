@@ -0,0 +1,120 @@
+//! Helpers shared by the integration tests: a sandbox with the fetcher deployed, a relayer
+//! configured against it and a local upstream for it to fetch from.
+
+// Each test crate compiles this module and only uses some of it.
+#![allow(dead_code)]
+
+use anyhow::{bail, Result};
+use near_workspaces::network::{NetworkInfo, Sandbox};
+use near_workspaces::{Account, Contract, Worker};
+use relayer::policy::UrlPolicy;
+use relayer::{process_once, Config as RelayerConfig};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::json;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::time::{sleep, Duration};
+
+#[derive(Deserialize)]
+struct RequestPage<T> {
+    requests: Vec<T>,
+}
+
+/// Deploys the fetcher to a fresh sandbox, initialized with a new account as its only relayer.
+pub async fn setup() -> Result<(Worker<Sandbox>, Contract, Account)> {
+    let fetcher_wasm = near_workspaces::compile_project("./").await?;
+    let worker = near_workspaces::sandbox().await?;
+
+    let relayer = worker.dev_create_account().await?;
+    let fetcher = worker.dev_deploy(&fetcher_wasm).await?;
+
+    fetcher
+        .call("new")
+        .args_json(json!({ "trusted_relayer": relayer.id() }))
+        .transact()
+        .await?
+        .into_result()?;
+
+    Ok((worker, fetcher, relayer))
+}
+
+/// A relayer for `fetcher` signing as `relayer`. Test upstreams are local, so it may reach
+/// private addresses.
+pub fn relayer_config(
+    worker: &Worker<Sandbox>,
+    fetcher: &Contract,
+    relayer: &Account,
+) -> Result<RelayerConfig> {
+    Ok(RelayerConfig::from_parts(
+        worker.info().rpc_url.as_str(),
+        fetcher.id().as_str(),
+        relayer.id().as_str(),
+        &relayer.secret_key().to_string(),
+        Some(1),
+    )?
+    .with_url_policy(UrlPolicy {
+        block_private_ips: false,
+        ..UrlPolicy::default()
+    }))
+}
+
+/// Runs the relayer until it has processed a pending request.
+pub async fn process_pending(config: &RelayerConfig) -> Result<()> {
+    let http_client = config.http_client()?;
+    for _ in 0..10 {
+        if process_once(config, &http_client).await? {
+            return Ok(());
+        }
+        sleep(Duration::from_millis(200)).await;
+    }
+    bail!("relayer did not pick up the request")
+}
+
+/// Polls `list_requests` until `count` requests are pending and returns them.
+pub async fn wait_for_requests<T: DeserializeOwned>(
+    fetcher: &Contract,
+    count: usize,
+) -> Result<Vec<T>> {
+    loop {
+        let page: RequestPage<T> = fetcher
+            .view("list_requests")
+            .args_json(json!({}))
+            .await?
+            .json()?;
+        if page.requests.len() >= count {
+            return Ok(page.requests);
+        }
+        sleep(Duration::from_millis(200)).await;
+    }
+}
+
+/// Waits for the first pending request, for tests that submit one fetch at a time.
+pub async fn wait_for_pending<T: DeserializeOwned>(fetcher: &Contract) -> Result<T> {
+    let requests = wait_for_requests(fetcher, 1).await?;
+    Ok(requests.into_iter().next().expect("one request is pending"))
+}
+
+/// Serves `body` with a Content-Length on a local port and returns the URL to fetch it from.
+pub async fn spawn_upstream(body: Vec<u8>) -> Result<String> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("http://{}/large", listener.local_addr()?);
+    tokio::spawn(serve_body(listener, body));
+    Ok(url)
+}
+
+async fn serve_body(listener: TcpListener, body: Vec<u8>) {
+    while let Ok((mut socket, _)) = listener.accept().await {
+        let body = body.clone();
+        tokio::spawn(async move {
+            let mut request = [0u8; 4096];
+            let _ = socket.read(&mut request).await;
+            let header = format!(
+                "HTTP/1.1 200 OK\r\ncontent-type: application/octet-stream\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+                body.len()
+            );
+            let _ = socket.write_all(header.as_bytes()).await;
+            let _ = socket.write_all(&body).await;
+        });
+    }
+}
@@ -0,0 +1,106 @@
+mod common;
+
+use anyhow::Result;
+use near_workspaces::network::NetworkInfo;
+use near_workspaces::types::NearToken;
+use relayer::events::{BlockFollower, FetchEvent};
+use relayer::process_events;
+use serde_json::json;
+use tokio::time::{sleep, Duration};
+
+#[tokio::test]
+async fn block_follower_reports_fetch_events() -> Result<()> {
+    let (worker, fetcher, relayer) = common::setup().await?;
+    let caller = worker.dev_create_account().await?;
+
+    let relayer_config = common::relayer_config(&worker, &fetcher, &relayer)?;
+    let http_client = relayer_config.http_client()?;
+    let mut follower = BlockFollower::new(
+        worker.info().rpc_url.clone(),
+        fetcher.id().as_str().parse()?,
+    );
+    // The first poll only pins the starting height.
+    assert!(follower.poll().await?.is_empty());
+
+    let fetch_tx = caller
+        .call(fetcher.id(), "fetch")
+        .args_json(json!({
+            "url": "https://example.com/",
+            "context": null
+        }))
+        .deposit(NearToken::from_millinear(100))
+        .max_gas()
+        .transact_async()
+        .await?;
+
+    let mut events = Vec::new();
+    for _ in 0..60 {
+        events.extend(follower.poll().await?);
+        if !events.is_empty() {
+            break;
+        }
+        sleep(Duration::from_millis(500)).await;
+    }
+    assert_eq!(events, vec![FetchEvent::Requested { request_id: 0 }]);
+
+    caller
+        .call(fetcher.id(), "cancel")
+        .args_json(json!({ "request_id": 0 }))
+        .transact()
+        .await?
+        .into_result()?;
+
+    let mut events = Vec::new();
+    for _ in 0..60 {
+        events.extend(follower.poll().await?);
+        if !events.is_empty() {
+            break;
+        }
+        sleep(Duration::from_millis(500)).await;
+    }
+    assert_eq!(events, vec![FetchEvent::Cancelled { request_id: 0 }]);
+
+    // The request is gone, so dispatching its event is a no-op.
+    let handled = process_events(
+        &relayer_config,
+        &http_client,
+        &[FetchEvent::Requested { request_id: 0 }],
+    )
+    .await?;
+    assert_eq!(handled, 0);
+
+    fetch_tx.await?.into_result()?;
+
+    // A fetch signed by the contract account itself runs as a local receipt, which only the
+    // chunk's transactions show.
+    let self_fetch_tx = fetcher
+        .call("fetch")
+        .args_json(json!({
+            "url": "https://example.com/",
+            "context": null
+        }))
+        .deposit(NearToken::from_millinear(100))
+        .max_gas()
+        .transact_async()
+        .await?;
+
+    let mut events = Vec::new();
+    for _ in 0..60 {
+        events.extend(follower.poll().await?);
+        if !events.is_empty() {
+            break;
+        }
+        sleep(Duration::from_millis(500)).await;
+    }
+    assert_eq!(events, vec![FetchEvent::Requested { request_id: 1 }]);
+
+    fetcher
+        .call("cancel")
+        .args_json(json!({ "request_id": 1 }))
+        .transact()
+        .await?
+        .into_result()?;
+    self_fetch_tx.await?.into_result()?;
+
+    Ok(())
+}
@@ -1,17 +1,58 @@
+mod common;
+
+use std::io::Write;
+
 use anyhow::Result;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use flate2::{write::GzEncoder, Compression};
+use near_workspaces::types::NearToken;
+use near_workspaces::Account;
 use serde::Deserialize;
 use serde_json::json;
+use sha2::{Digest, Sha256};
+
+/// Covers storage for the small requests and bodies used in these tests; unused deposit is
+/// refunded when the request completes.
+const FETCH_DEPOSIT: NearToken = NearToken::from_millinear(100);
+
+#[derive(Clone, Deserialize)]
+struct HttpHeaderView {
+    name: String,
+    value: String,
+}
+
+#[derive(Deserialize)]
+struct ResponseProgressView {
+    length: u64,
+    sha256: Vec<u8>,
+}
 
 #[derive(Clone, Deserialize)]
 struct PendingRequestView {
     request_id: u64,
     url: String,
+    method: String,
+    headers: Vec<HttpHeaderView>,
+    #[serde(default)]
+    body: Option<Vec<u8>>,
     #[allow(dead_code)]
     caller: String,
     #[serde(default)]
     #[allow(dead_code)]
     context: Option<Vec<u8>>,
     yield_id: Vec<u8>,
+    created_at_height: u64,
+    deadline_height: u64,
+    #[serde(default)]
+    extract: Option<Vec<String>>,
+    #[serde(default)]
+    voters: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct RequestPageView {
+    requests: Vec<PendingRequestView>,
+    last_scanned_id: Option<u64>,
 }
 
 #[derive(Deserialize)]
@@ -20,33 +61,52 @@ struct FetchResultView {
     url: String,
     status: FetchStatusView,
     #[serde(default)]
+    status_code: Option<u16>,
+    #[serde(default)]
+    headers: Vec<HttpHeaderView>,
+    #[serde(default)]
     body: Option<Vec<u8>>,
     #[serde(default)]
+    body_hash: Option<Vec<u8>>,
+    #[serde(default)]
+    body_encoding: Option<String>,
+    #[serde(default)]
+    extract: Option<Vec<String>>,
+    #[serde(default)]
+    extraction: Option<serde_json::Value>,
+    #[serde(default)]
     #[allow(dead_code)]
     context: Option<Vec<u8>>,
     caller: String,
+    #[serde(default)]
+    consensus: Option<ConsensusReportView>,
+}
+
+#[derive(Deserialize)]
+struct ConsensusReportView {
+    quorum: u8,
+    accepted_hash: Option<Vec<u8>>,
+    mismatched: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct UrlPolicyView {
+    allowed_schemes: Vec<String>,
+    allowed_domains: Vec<String>,
+    max_url_length: u32,
 }
 
 #[derive(Deserialize)]
 enum FetchStatusView {
     Completed,
     TimedOut,
+    Failed { kind: String, message: String },
+    Cancelled,
 }
 
 #[tokio::test]
 async fn fetcher_yield_resume_flow() -> Result<()> {
-    let fetcher_wasm = near_workspaces::compile_project("./").await?;
-    let worker = near_workspaces::sandbox().await?;
-
-    let relayer = worker.dev_create_account().await?;
-    let fetcher = worker.dev_deploy(&fetcher_wasm).await?;
-
-    fetcher
-        .call("new")
-        .args_json(json!({ "trusted_relayer": relayer.id() }))
-        .transact()
-        .await?
-        .into_result()?;
+    let (_worker, fetcher, relayer) = common::setup().await?;
 
     let fetch_tx = fetcher
         .call("fetch")
@@ -54,21 +114,12 @@ async fn fetcher_yield_resume_flow() -> Result<()> {
             "url": "https://example.com/data",
             "context": null
         }))
+        .deposit(FETCH_DEPOSIT)
         .max_gas()
         .transact_async()
         .await?;
 
-    let pending = loop {
-        let requests: Vec<PendingRequestView> = fetcher
-            .view("list_requests")
-            .args_json(json!({}))
-            .await?
-            .json()?;
-        if let Some(first) = requests.first() {
-            break first.clone();
-        }
-        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
-    };
+    let pending: PendingRequestView = common::wait_for_pending(&fetcher).await?;
 
     let response_payload = br#"{"status":"ok"}"#.to_vec();
     relayer
@@ -76,7 +127,7 @@ async fn fetcher_yield_resume_flow() -> Result<()> {
         .args_json(json!({
             "request_id": pending.request_id,
             "data": response_payload.clone(),
-            "append": false,
+            "offset": 0,
         }))
         .max_gas()
         .transact()
@@ -99,6 +150,8 @@ async fn fetcher_yield_resume_flow() -> Result<()> {
     match fetch_result.status {
         FetchStatusView::Completed => (),
         FetchStatusView::TimedOut => panic!("fetch unexpectedly timed out"),
+        FetchStatusView::Failed { .. } => panic!("fetch unexpectedly failed"),
+        FetchStatusView::Cancelled => panic!("fetch unexpectedly cancelled"),
     }
 
     let body_bytes = fetch_result
@@ -106,15 +159,20 @@ async fn fetcher_yield_resume_flow() -> Result<()> {
         .as_ref()
         .expect("body should be present in completed result");
     assert_eq!(body_bytes, &response_payload);
+    assert_eq!(
+        fetch_result.body_hash,
+        Some(Sha256::digest(&response_payload).to_vec())
+    );
     assert_eq!(fetch_result.request_id, pending.request_id);
     assert_eq!(fetch_result.url, pending.url);
     assert_eq!(fetch_result.caller, fetcher.id().to_string());
 
-    let remaining: Vec<PendingRequestView> = fetcher
+    let remaining = fetcher
         .view("list_requests")
         .args_json(json!({}))
         .await?
-        .json()?;
+        .json::<RequestPageView>()?
+        .requests;
     assert!(
         remaining.is_empty(),
         "requests should be cleared after resume"
@@ -122,3 +180,1479 @@ async fn fetcher_yield_resume_flow() -> Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn respond_propagates_status_code_and_headers() -> Result<()> {
+    let (_worker, fetcher, relayer) = common::setup().await?;
+
+    let fetch_tx = fetcher
+        .call("fetch")
+        .args_json(json!({
+            "url": "https://example.com/missing",
+            "context": null
+        }))
+        .deposit(FETCH_DEPOSIT)
+        .max_gas()
+        .transact_async()
+        .await?;
+
+    let pending: PendingRequestView = common::wait_for_pending(&fetcher).await?;
+
+    let response_payload = br#"{"error":"not found"}"#.to_vec();
+    relayer
+        .call(fetcher.id(), "respond")
+        .args_json(json!({
+            "request_id": pending.request_id,
+            "yield_id": pending.yield_id.clone(),
+            "body": response_payload.clone(),
+            "status_code": 404,
+            "headers": [{ "name": "content-type", "value": "application/json" }],
+        }))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+
+    // A non-2xx status is still a completed fetch; the caller decides what it means.
+    let fetch_result: FetchResultView = fetch_tx.await?.json()?;
+    assert!(matches!(fetch_result.status, FetchStatusView::Completed));
+    assert_eq!(fetch_result.status_code, Some(404));
+    assert_eq!(fetch_result.headers.len(), 1);
+    assert_eq!(fetch_result.headers[0].name, "content-type");
+    assert_eq!(fetch_result.headers[0].value, "application/json");
+    assert_eq!(fetch_result.body, Some(response_payload));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn fetch_records_http_request_spec() -> Result<()> {
+    let (_worker, fetcher, _relayer) = common::setup().await?;
+
+    let request_body = br#"{"query":"ping"}"#.to_vec();
+    let _fetch_tx = fetcher
+        .call("fetch")
+        .args_json(json!({
+            "url": "https://example.com/graphql",
+            "context": null,
+            "request": {
+                "method": "Post",
+                "headers": [{ "name": "content-type", "value": "application/json" }],
+                "body": request_body.clone(),
+            }
+        }))
+        .deposit(FETCH_DEPOSIT)
+        .max_gas()
+        .transact_async()
+        .await?;
+
+    let pending: PendingRequestView = common::wait_for_pending(&fetcher).await?;
+
+    assert_eq!(pending.method, "Post");
+    assert_eq!(pending.headers.len(), 1);
+    assert_eq!(pending.headers[0].name, "content-type");
+    assert_eq!(pending.headers[0].value, "application/json");
+    assert_eq!(pending.body, Some(request_body));
+
+    let rejected = fetcher
+        .call("fetch")
+        .args_json(json!({
+            "url": "https://example.com/data",
+            "context": null,
+            "request": { "method": "Get", "body": [1, 2, 3] }
+        }))
+        .deposit(FETCH_DEPOSIT)
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(rejected.is_failure(), "GET with a body should be rejected");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn respond_error_fails_fetch_immediately() -> Result<()> {
+    let (_worker, fetcher, relayer) = common::setup().await?;
+
+    let fetch_tx = fetcher
+        .call("fetch")
+        .args_json(json!({
+            "url": "https://unreachable.invalid/",
+            "context": null
+        }))
+        .deposit(FETCH_DEPOSIT)
+        .max_gas()
+        .transact_async()
+        .await?;
+
+    let pending: PendingRequestView = common::wait_for_pending(&fetcher).await?;
+
+    relayer
+        .call(fetcher.id(), "respond_error")
+        .args_json(json!({
+            "request_id": pending.request_id,
+            "yield_id": pending.yield_id.clone(),
+            "error_kind": "Connection",
+            "message": "dns error: failed to lookup address information",
+        }))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+
+    let fetch_result: FetchResultView = fetch_tx.await?.json()?;
+    match fetch_result.status {
+        FetchStatusView::Failed { kind, message } => {
+            assert_eq!(kind, "Connection");
+            assert!(message.contains("dns error"));
+        }
+        _ => panic!("fetch should report the relayer failure"),
+    }
+    assert!(fetch_result.body.is_none());
+    assert_eq!(fetch_result.status_code, None);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn cancel_resolves_with_cancelled_status() -> Result<()> {
+    let (worker, fetcher, relayer) = common::setup().await?;
+    let caller = worker.dev_create_account().await?;
+
+    let fetch_tx = caller
+        .call(fetcher.id(), "fetch")
+        .args_json(json!({
+            "url": "https://example.com/slow",
+            "context": null
+        }))
+        .deposit(FETCH_DEPOSIT)
+        .max_gas()
+        .transact_async()
+        .await?;
+
+    let pending: PendingRequestView = common::wait_for_pending(&fetcher).await?;
+
+    let foreign_cancel = relayer
+        .call(fetcher.id(), "cancel")
+        .args_json(json!({ "request_id": pending.request_id }))
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(
+        foreign_cancel.is_failure(),
+        "only the caller should be able to cancel"
+    );
+
+    let cancel = caller
+        .call(fetcher.id(), "cancel")
+        .args_json(json!({ "request_id": pending.request_id }))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+    assert!(cancel
+        .logs()
+        .iter()
+        .any(|log| log.contains("fetch_cancelled")));
+
+    let fetch_result: FetchResultView = fetch_tx.await?.json()?;
+    assert!(matches!(fetch_result.status, FetchStatusView::Cancelled));
+    assert!(fetch_result.body.is_none());
+
+    let late_response = relayer
+        .call(fetcher.id(), "respond")
+        .args_json(json!({
+            "request_id": pending.request_id,
+            "yield_id": pending.yield_id,
+            "body": [1, 2, 3],
+        }))
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(
+        late_response.is_failure(),
+        "responding to a cancelled request should fail"
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn relayer_registry_is_owner_managed() -> Result<()> {
+    let (worker, fetcher, relayer) = common::setup().await?;
+    let backup_relayer = worker.dev_create_account().await?;
+    let outsider = worker.dev_create_account().await?;
+
+    let owner: String = fetcher.view("owner").args_json(json!({})).await?.json()?;
+    assert_eq!(owner, fetcher.id().to_string());
+
+    let unauthorized = outsider
+        .call(fetcher.id(), "add_relayer")
+        .args_json(json!({ "account_id": outsider.id() }))
+        .transact()
+        .await?;
+    assert!(unauthorized.is_failure(), "only the owner may add relayers");
+
+    let added = fetcher
+        .call("add_relayer")
+        .args_json(json!({ "account_id": backup_relayer.id() }))
+        .transact()
+        .await?
+        .into_result()?;
+    assert!(added.logs().iter().any(|log| log.contains("relayer_added")));
+
+    fetcher
+        .call("remove_relayer")
+        .args_json(json!({ "account_id": relayer.id() }))
+        .transact()
+        .await?
+        .into_result()?;
+
+    let relayers: Vec<String> = fetcher
+        .view("list_relayers")
+        .args_json(json!({}))
+        .await?
+        .json()?;
+    assert_eq!(relayers, vec![backup_relayer.id().to_string()]);
+
+    // The single-relayer view still answers for existing clients.
+    let trusted: String = fetcher
+        .view("trusted_relayer")
+        .args_json(json!({}))
+        .await?
+        .json()?;
+    assert_eq!(trusted, backup_relayer.id().to_string());
+
+    // Deploys call migrate on every upgrade; state in the current layout is left alone.
+    fetcher
+        .call("migrate")
+        .args_json(json!({}))
+        .transact()
+        .await?
+        .into_result()?;
+    let relayers: Vec<String> = fetcher
+        .view("list_relayers")
+        .args_json(json!({}))
+        .await?
+        .json()?;
+    assert_eq!(relayers, vec![backup_relayer.id().to_string()]);
+
+    let fetch_tx = fetcher
+        .call("fetch")
+        .args_json(json!({
+            "url": "https://example.com/data",
+            "context": null
+        }))
+        .deposit(FETCH_DEPOSIT)
+        .max_gas()
+        .transact_async()
+        .await?;
+
+    let pending: PendingRequestView = common::wait_for_pending(&fetcher).await?;
+
+    let respond_args = json!({
+        "request_id": pending.request_id,
+        "yield_id": pending.yield_id.clone(),
+        "body": b"ok".to_vec(),
+        "status_code": 200,
+    });
+
+    let removed = relayer
+        .call(fetcher.id(), "respond")
+        .args_json(respond_args.clone())
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(removed.is_failure(), "removed relayer must not respond");
+
+    backup_relayer
+        .call(fetcher.id(), "respond")
+        .args_json(respond_args)
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+
+    let fetch_result: FetchResultView = fetch_tx.await?.json()?;
+    assert!(matches!(fetch_result.status, FetchStatusView::Completed));
+    assert_eq!(fetch_result.body, Some(b"ok".to_vec()));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn quorum_request_waits_for_matching_hashes() -> Result<()> {
+    let (worker, fetcher, relayer) = common::setup().await?;
+    let relayers = [
+        relayer,
+        worker.dev_create_account().await?,
+        worker.dev_create_account().await?,
+    ];
+    for relayer in &relayers[1..] {
+        fetcher
+            .call("add_relayer")
+            .args_json(json!({ "account_id": relayer.id() }))
+            .transact()
+            .await?
+            .into_result()?;
+    }
+
+    let fetch_tx = fetcher
+        .call("fetch")
+        .args_json(json!({
+            "url": "https://example.com/price",
+            "context": null,
+            "options": { "quorum": 2 }
+        }))
+        .deposit(FETCH_DEPOSIT)
+        .max_gas()
+        .transact_async()
+        .await?;
+
+    let pending: PendingRequestView = common::wait_for_pending(&fetcher).await?;
+
+    let honest = br#"{"price":100}"#.to_vec();
+    let dishonest = br#"{"price":1}"#.to_vec();
+    let submissions = [(&relayers[0], &honest), (&relayers[1], &dishonest)];
+    for (relayer, body) in submissions {
+        relayer
+            .call(fetcher.id(), "respond")
+            .args_json(json!({
+                "request_id": pending.request_id,
+                "yield_id": pending.yield_id.clone(),
+                "body": body,
+                "status_code": 200,
+                "body_hash": Sha256::digest(body).to_vec(),
+            }))
+            .max_gas()
+            .transact()
+            .await?
+            .into_result()?;
+    }
+
+    let still_pending = fetcher
+        .view("list_requests")
+        .args_json(json!({}))
+        .await?
+        .json::<RequestPageView>()?
+        .requests;
+    assert_eq!(still_pending.len(), 1, "quorum not reached yet");
+    assert_eq!(still_pending[0].voters.len(), 2);
+
+    relayers[2]
+        .call(fetcher.id(), "respond")
+        .args_json(json!({
+            "request_id": pending.request_id,
+            "yield_id": pending.yield_id.clone(),
+            "body": honest.clone(),
+            "status_code": 200,
+            "body_hash": Sha256::digest(&honest).to_vec(),
+        }))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+
+    let fetch_result: FetchResultView = fetch_tx.await?.json()?;
+    assert!(matches!(fetch_result.status, FetchStatusView::Completed));
+    assert_eq!(fetch_result.body, Some(honest.clone()));
+
+    let consensus = fetch_result
+        .consensus
+        .expect("quorum requests report consensus");
+    assert_eq!(consensus.quorum, 2);
+    assert_eq!(
+        consensus.accepted_hash,
+        Some(Sha256::digest(&honest).to_vec())
+    );
+    assert_eq!(consensus.mismatched, vec![relayers[1].id().to_string()]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn quorum_relayers_must_agree_on_the_status_as_well_as_the_body() -> Result<()> {
+    let (worker, fetcher, relayer) = common::setup().await?;
+    let relayers = [relayer, worker.dev_create_account().await?];
+    fetcher
+        .call("add_relayer")
+        .args_json(json!({ "account_id": relayers[1].id() }))
+        .transact()
+        .await?
+        .into_result()?;
+
+    let fetch_tx = fetcher
+        .call("fetch")
+        .args_json(json!({
+            "url": "https://example.com/price",
+            "context": null,
+            "options": { "quorum": 2 }
+        }))
+        .deposit(FETCH_DEPOSIT)
+        .max_gas()
+        .transact_async()
+        .await?;
+    let pending: PendingRequestView = common::wait_for_pending(&fetcher).await?;
+
+    // Same body, but the second relayer claims the upstream failed.
+    let body = br#"{"price":100}"#.to_vec();
+    for (relayer, status_code) in [(&relayers[0], 200), (&relayers[1], 500)] {
+        relayer
+            .call(fetcher.id(), "respond")
+            .args_json(json!({
+                "request_id": pending.request_id,
+                "yield_id": pending.yield_id.clone(),
+                "body": body,
+                "status_code": status_code,
+                "body_hash": Sha256::digest(&body).to_vec(),
+            }))
+            .max_gas()
+            .transact()
+            .await?
+            .into_result()?;
+    }
+
+    let fetch_result: FetchResultView = fetch_tx.await?.json()?;
+    match fetch_result.status {
+        FetchStatusView::Failed { kind, .. } => assert_eq!(kind, "NoConsensus"),
+        _ => panic!("relayers disagreeing on the status must not reach the quorum"),
+    }
+    assert_eq!(fetch_result.body, None);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn quorum_relayers_upload_separately_and_agreed_errors_are_reported() -> Result<()> {
+    let (worker, fetcher, relayer) = common::setup().await?;
+    let relayers = [
+        relayer,
+        worker.dev_create_account().await?,
+        worker.dev_create_account().await?,
+    ];
+    for relayer in &relayers[1..] {
+        fetcher
+            .call("add_relayer")
+            .args_json(json!({ "account_id": relayer.id() }))
+            .transact()
+            .await?
+            .into_result()?;
+    }
+
+    let fetch_tx = fetcher
+        .call("fetch")
+        .args_json(json!({
+            "url": "https://example.com/price",
+            "context": null,
+            "options": { "quorum": 2 }
+        }))
+        .deposit(FETCH_DEPOSIT)
+        .max_gas()
+        .transact_async()
+        .await?;
+    let pending: PendingRequestView = common::wait_for_pending(&fetcher).await?;
+
+    let body = br#"{"price":100}"#.to_vec();
+    let (head, tail) = body.split_at(6);
+    let store_chunk = |relayer: &Account, data: &[u8], offset: usize| {
+        relayer
+            .call(fetcher.id(), "store_response_chunk")
+            .args_json(json!({
+                "request_id": pending.request_id,
+                "data": data,
+                "offset": offset,
+            }))
+            .max_gas()
+            .transact()
+    };
+
+    // The third relayer's upload and error land between the other two relayers' chunks.
+    store_chunk(&relayers[0], head, 0).await?.into_result()?;
+    store_chunk(&relayers[2], b"garbage", 0)
+        .await?
+        .into_result()?;
+    store_chunk(&relayers[1], head, 0).await?.into_result()?;
+    relayers[2]
+        .call(fetcher.id(), "respond_error")
+        .args_json(json!({
+            "request_id": pending.request_id,
+            "yield_id": pending.yield_id.clone(),
+            "error_kind": "Body",
+            "message": "connection reset while reading the body",
+        }))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+    for relayer in &relayers[..2] {
+        store_chunk(relayer, tail, head.len())
+            .await?
+            .into_result()?;
+        relayer
+            .call(fetcher.id(), "respond")
+            .args_json(json!({
+                "request_id": pending.request_id,
+                "yield_id": pending.yield_id.clone(),
+                "status_code": 200,
+                "body_hash": Sha256::digest(&body).to_vec(),
+            }))
+            .max_gas()
+            .transact()
+            .await?
+            .into_result()?;
+    }
+
+    let fetch_result: FetchResultView = fetch_tx.await?.json()?;
+    assert!(matches!(fetch_result.status, FetchStatusView::Completed));
+    assert_eq!(fetch_result.body, Some(body));
+
+    // When every relayer fails the same way, the request reports that failure.
+    let fetch_tx = fetcher
+        .call("fetch")
+        .args_json(json!({
+            "url": "https://example.com/down",
+            "context": null,
+            "options": { "quorum": 2 }
+        }))
+        .deposit(FETCH_DEPOSIT)
+        .max_gas()
+        .transact_async()
+        .await?;
+    let pending: PendingRequestView = common::wait_for_pending(&fetcher).await?;
+    for relayer in &relayers[..2] {
+        relayer
+            .call(fetcher.id(), "respond_error")
+            .args_json(json!({
+                "request_id": pending.request_id,
+                "yield_id": pending.yield_id.clone(),
+                "error_kind": "Connection",
+                "message": "connection refused",
+            }))
+            .max_gas()
+            .transact()
+            .await?
+            .into_result()?;
+    }
+
+    let fetch_result: FetchResultView = fetch_tx.await?.json()?;
+    match fetch_result.status {
+        FetchStatusView::Failed { kind, .. } => assert_eq!(kind, "Connection"),
+        _ => panic!("fetch should report the relayers' failure"),
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn list_requests_paginates_and_filters() -> Result<()> {
+    let (worker, fetcher, _relayer) = common::setup().await?;
+    let other_caller = worker.dev_create_account().await?;
+
+    let mut pending_fetches = Vec::new();
+    for path in ["a", "b"] {
+        pending_fetches.push(
+            fetcher
+                .call("fetch")
+                .args_json(json!({ "url": format!("https://example.com/{path}"), "context": null }))
+                .deposit(FETCH_DEPOSIT)
+                .max_gas()
+                .transact_async()
+                .await?,
+        );
+    }
+    pending_fetches.push(
+        other_caller
+            .call(fetcher.id(), "fetch")
+            .args_json(json!({ "url": "https://example.com/c", "context": null }))
+            .deposit(FETCH_DEPOSIT)
+            .max_gas()
+            .transact_async()
+            .await?,
+    );
+
+    let all: Vec<PendingRequestView> = common::wait_for_requests(&fetcher, 3).await?;
+
+    let first_page: RequestPageView = fetcher
+        .view("list_requests")
+        .args_json(json!({ "limit": 2 }))
+        .await?
+        .json()?;
+    assert_eq!(first_page.requests.len(), 2);
+    let cursor = first_page
+        .last_scanned_id
+        .expect("a full page has a cursor");
+    let second_page: RequestPageView = fetcher
+        .view("list_requests")
+        .args_json(json!({ "min_request_id": cursor + 1, "limit": 2 }))
+        .await?
+        .json()?;
+    assert_eq!(second_page.requests.len(), 1);
+    assert_eq!(second_page.last_scanned_id, None);
+
+    // Pages come in id order and continue from the last id scanned.
+    let paged: Vec<u64> = first_page
+        .requests
+        .iter()
+        .chain(second_page.requests.iter())
+        .map(|request| request.request_id)
+        .collect();
+    let mut expected: Vec<u64> = all.iter().map(|request| request.request_id).collect();
+    expected.sort_unstable();
+    assert_eq!(paged, expected);
+
+    // The limit counts requests read, filtered out or not, so pages can come back short or
+    // empty before the cursor runs out.
+    let mut by_caller = Vec::new();
+    let mut pages = 0;
+    let mut min_request_id = 0;
+    loop {
+        let page: RequestPageView = fetcher
+            .view("list_requests")
+            .args_json(json!({
+                "caller": other_caller.id(),
+                "min_request_id": min_request_id,
+                "limit": 1,
+            }))
+            .await?
+            .json()?;
+        pages += 1;
+        by_caller.extend(page.requests);
+        match page.last_scanned_id {
+            Some(last_scanned_id) => min_request_id = last_scanned_id + 1,
+            None => break,
+        }
+    }
+    assert_eq!(pages, expected.len() + 1);
+    assert_eq!(by_caller.len(), 1);
+    assert_eq!(by_caller[0].url, "https://example.com/c");
+
+    let max_id = *expected.last().unwrap();
+    let newest: RequestPageView = fetcher
+        .view("list_requests")
+        .args_json(json!({ "min_request_id": max_id }))
+        .await?
+        .json()?;
+    assert_eq!(newest.requests.len(), 1);
+    assert_eq!(newest.requests[0].request_id, max_id);
+
+    let single: Option<PendingRequestView> = fetcher
+        .view("get_request")
+        .args_json(json!({ "request_id": max_id }))
+        .await?
+        .json()?;
+    assert_eq!(single.map(|request| request.request_id), Some(max_id));
+
+    let missing: Option<PendingRequestView> = fetcher
+        .view("get_request")
+        .args_json(json!({ "request_id": max_id + 100 }))
+        .await?
+        .json()?;
+    assert!(missing.is_none());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn fees_accrue_to_relayer_and_can_be_withdrawn() -> Result<()> {
+    let (_worker, fetcher, relayer) = common::setup().await?;
+
+    let fee = NearToken::from_millinear(10);
+    fetcher
+        .call("set_fee_schedule")
+        .args_json(json!({
+            "fee_schedule": { "base": fee, "per_kilobyte": NearToken::from_yoctonear(0) }
+        }))
+        .transact()
+        .await?
+        .into_result()?;
+
+    let unpaid = fetcher
+        .call("fetch")
+        .args_json(json!({ "url": "https://example.com/data", "context": null }))
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(unpaid.is_failure(), "fetch without the fee should fail");
+
+    let fetch_tx = fetcher
+        .call("fetch")
+        .args_json(json!({ "url": "https://example.com/data", "context": null }))
+        .deposit(NearToken::from_millinear(50))
+        .max_gas()
+        .transact_async()
+        .await?;
+
+    let pending: PendingRequestView = common::wait_for_pending(&fetcher).await?;
+
+    relayer
+        .call(fetcher.id(), "respond")
+        .args_json(json!({
+            "request_id": pending.request_id,
+            "yield_id": pending.yield_id.clone(),
+            "body": b"ok".to_vec(),
+            "status_code": 200,
+        }))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+
+    let fetch_result: FetchResultView = fetch_tx.await?.json()?;
+    assert!(matches!(fetch_result.status, FetchStatusView::Completed));
+
+    let accrued: NearToken = fetcher
+        .view("get_accrued_fees")
+        .args_json(json!({ "account_id": relayer.id() }))
+        .await?
+        .json()?;
+    assert_eq!(accrued, fee);
+
+    // A relayer-side failure is refunded to the caller instead of earning the fee.
+    let fetch_tx = fetcher
+        .call("fetch")
+        .args_json(json!({ "url": "https://example.com/down", "context": null }))
+        .deposit(NearToken::from_millinear(50))
+        .max_gas()
+        .transact_async()
+        .await?;
+    let pending: PendingRequestView = common::wait_for_pending(&fetcher).await?;
+    relayer
+        .call(fetcher.id(), "respond_error")
+        .args_json(json!({
+            "request_id": pending.request_id,
+            "yield_id": pending.yield_id.clone(),
+            "error_kind": "Connection",
+            "message": "connection refused",
+        }))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+    let fetch_result: FetchResultView = fetch_tx.await?.json()?;
+    assert!(matches!(
+        fetch_result.status,
+        FetchStatusView::Failed { .. }
+    ));
+
+    let accrued: NearToken = fetcher
+        .view("get_accrued_fees")
+        .args_json(json!({ "account_id": relayer.id() }))
+        .await?
+        .json()?;
+    assert_eq!(accrued, fee);
+
+    relayer
+        .call(fetcher.id(), "withdraw_fees")
+        .args_json(json!({}))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+
+    let remaining: NearToken = fetcher
+        .view("get_accrued_fees")
+        .args_json(json!({ "account_id": relayer.id() }))
+        .await?
+        .json()?;
+    assert_eq!(remaining, NearToken::from_yoctonear(0));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn response_storage_is_charged_to_deposit() -> Result<()> {
+    let (_worker, fetcher, relayer) = common::setup().await?;
+
+    let underfunded = fetcher
+        .call("fetch")
+        .args_json(json!({ "url": "https://example.com/data", "context": null }))
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(underfunded.is_failure(), "request storage must be paid for");
+
+    // 10 mNEAR pays for roughly 1 KB of storage, including the request itself.
+    let fetch_tx = fetcher
+        .call("fetch")
+        .args_json(json!({ "url": "https://example.com/data", "context": null }))
+        .deposit(NearToken::from_millinear(10))
+        .max_gas()
+        .transact_async()
+        .await?;
+
+    let pending: PendingRequestView = common::wait_for_pending(&fetcher).await?;
+
+    let oversized = relayer
+        .call(fetcher.id(), "store_response_chunk")
+        .args_json(json!({
+            "request_id": pending.request_id,
+            "data": vec![b'x'; 2_000],
+            "offset": 0,
+        }))
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(
+        oversized.is_failure(),
+        "body storage beyond the deposit must be rejected"
+    );
+
+    relayer
+        .call(fetcher.id(), "store_response_chunk")
+        .args_json(json!({
+            "request_id": pending.request_id,
+            "data": b"small".to_vec(),
+            "offset": 0,
+        }))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+
+    relayer
+        .call(fetcher.id(), "respond")
+        .args_json(json!({
+            "request_id": pending.request_id,
+            "yield_id": pending.yield_id.clone(),
+            "body": json!(null),
+            "status_code": 200,
+        }))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+
+    let fetch_result: FetchResultView = fetch_tx.await?.json()?;
+    assert!(matches!(fetch_result.status, FetchStatusView::Completed));
+    assert_eq!(fetch_result.body, Some(b"small".to_vec()));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn chunk_uploads_resume_at_offset() -> Result<()> {
+    let (_worker, fetcher, relayer) = common::setup().await?;
+
+    let fetch_tx = fetcher
+        .call("fetch")
+        .args_json(json!({ "url": "https://example.com/large", "context": null }))
+        .deposit(FETCH_DEPOSIT)
+        .max_gas()
+        .transact_async()
+        .await?;
+
+    let pending: PendingRequestView = common::wait_for_pending(&fetcher).await?;
+
+    let progress: ResponseProgressView = fetcher
+        .view("get_response_progress")
+        .args_json(json!({ "request_id": pending.request_id, "relayer": relayer.id() }))
+        .await?
+        .json()?;
+    assert_eq!(progress.length, 0);
+
+    relayer
+        .call(fetcher.id(), "store_response_chunk")
+        .args_json(json!({
+            "request_id": pending.request_id,
+            "data": b"hello ".to_vec(),
+            "offset": 0,
+        }))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+
+    let gap = relayer
+        .call(fetcher.id(), "store_response_chunk")
+        .args_json(json!({
+            "request_id": pending.request_id,
+            "data": b"world".to_vec(),
+            "offset": 20,
+        }))
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(gap.is_failure(), "chunks must not leave gaps");
+
+    let progress: ResponseProgressView = fetcher
+        .view("get_response_progress")
+        .args_json(json!({ "request_id": pending.request_id, "relayer": relayer.id() }))
+        .await?
+        .json()?;
+    assert_eq!(progress.length, 6);
+    assert_eq!(progress.sha256, Sha256::digest(b"hello ").to_vec());
+
+    // Re-sending a chunk overwrites it instead of appending a second copy.
+    for data in [b"world".to_vec(), b"there".to_vec()] {
+        relayer
+            .call(fetcher.id(), "store_response_chunk")
+            .args_json(json!({
+                "request_id": pending.request_id,
+                "data": data,
+                "offset": progress.length,
+            }))
+            .max_gas()
+            .transact()
+            .await?
+            .into_result()?;
+    }
+
+    let wrong_length = relayer
+        .call(fetcher.id(), "respond")
+        .args_json(json!({
+            "request_id": pending.request_id,
+            "yield_id": pending.yield_id.clone(),
+            "body": json!(null),
+            "status_code": 200,
+            "body_len": 16,
+        }))
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(
+        wrong_length.is_failure(),
+        "respond must reject a body of the wrong length"
+    );
+
+    relayer
+        .call(fetcher.id(), "respond")
+        .args_json(json!({
+            "request_id": pending.request_id,
+            "yield_id": pending.yield_id.clone(),
+            "body": json!(null),
+            "status_code": 200,
+            "body_hash": Sha256::digest(b"hello there").to_vec(),
+            "body_len": 11,
+        }))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+
+    let fetch_result: FetchResultView = fetch_tx.await?.json()?;
+    assert!(matches!(fetch_result.status, FetchStatusView::Completed));
+    assert_eq!(fetch_result.body, Some(b"hello there".to_vec()));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn responses_after_the_deadline_are_rejected() -> Result<()> {
+    let (worker, fetcher, relayer) = common::setup().await?;
+    let anyone = worker.dev_create_account().await?;
+
+    let too_long = fetcher
+        .call("fetch")
+        .args_json(json!({
+            "url": "https://example.com/",
+            "options": { "timeout_blocks": 1_000 },
+        }))
+        .deposit(FETCH_DEPOSIT)
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(
+        too_long.is_failure(),
+        "deadlines beyond the yield timeout must be rejected"
+    );
+
+    let fetch_tx = fetcher
+        .call("fetch")
+        .args_json(json!({
+            "url": "https://example.com/",
+            "options": { "timeout_blocks": 10 },
+        }))
+        .deposit(FETCH_DEPOSIT)
+        .max_gas()
+        .transact_async()
+        .await?;
+
+    let pending: PendingRequestView = common::wait_for_pending(&fetcher).await?;
+    assert_eq!(pending.deadline_height, pending.created_at_height + 10);
+
+    let early = anyone
+        .call(fetcher.id(), "expire")
+        .args_json(json!({ "request_id": pending.request_id }))
+        .transact()
+        .await?;
+    assert!(
+        early.is_failure(),
+        "requests can't expire before the deadline"
+    );
+
+    worker.fast_forward(20).await?;
+
+    let late = relayer
+        .call(fetcher.id(), "respond")
+        .args_json(json!({
+            "request_id": pending.request_id,
+            "yield_id": pending.yield_id.clone(),
+            "body": b"too late".to_vec(),
+            "status_code": 200,
+        }))
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(
+        late.is_failure(),
+        "responses after the deadline must be rejected"
+    );
+
+    anyone
+        .call(fetcher.id(), "expire")
+        .args_json(json!({ "request_id": pending.request_id }))
+        .transact()
+        .await?
+        .into_result()?;
+
+    let fetch_result: FetchResultView = fetch_tx.await?.json()?;
+    assert!(matches!(fetch_result.status, FetchStatusView::TimedOut));
+    assert!(fetch_result.body.is_none());
+
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct PruneReportView {
+    bodies_removed: u32,
+    requests_removed: u32,
+}
+
+#[tokio::test]
+async fn chunks_require_a_pending_request_and_prune_is_restricted() -> Result<()> {
+    let (worker, fetcher, relayer) = common::setup().await?;
+    let stranger = worker.dev_create_account().await?;
+
+    let unknown = relayer
+        .call(fetcher.id(), "store_response_chunk")
+        .args_json(json!({ "request_id": 42, "data": b"orphan".to_vec(), "offset": 0 }))
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(
+        unknown.is_failure(),
+        "chunks for unknown requests must be rejected"
+    );
+
+    let fetch_tx = fetcher
+        .call("fetch")
+        .args_json(json!({ "url": "https://example.com/", "context": null }))
+        .deposit(FETCH_DEPOSIT)
+        .max_gas()
+        .transact_async()
+        .await?;
+
+    let pending: PendingRequestView = common::wait_for_pending(&fetcher).await?;
+
+    relayer
+        .call(fetcher.id(), "respond")
+        .args_json(json!({
+            "request_id": pending.request_id,
+            "yield_id": pending.yield_id.clone(),
+            "body": b"done".to_vec(),
+            "status_code": 200,
+        }))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+    let fetch_result: FetchResultView = fetch_tx.await?.json()?;
+    assert!(matches!(fetch_result.status, FetchStatusView::Completed));
+
+    let after_completion = relayer
+        .call(fetcher.id(), "store_response_chunk")
+        .args_json(json!({
+            "request_id": pending.request_id,
+            "data": b"late".to_vec(),
+            "offset": 0,
+        }))
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(
+        after_completion.is_failure(),
+        "chunks for completed requests must be rejected"
+    );
+
+    let denied = stranger
+        .call(fetcher.id(), "prune")
+        .args_json(json!({}))
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(denied.is_failure(), "only the owner or relayers can prune");
+
+    let report: PruneReportView = relayer
+        .call(fetcher.id(), "prune")
+        .args_json(json!({}))
+        .max_gas()
+        .transact()
+        .await?
+        .json()?;
+    assert_eq!(report.bodies_removed, 0);
+    assert_eq!(report.requests_removed, 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn fetch_enforces_the_url_policy() -> Result<()> {
+    let (worker, fetcher, _relayer) = common::setup().await?;
+    let caller = worker.dev_create_account().await?;
+
+    let policy = json!({
+        "allowed_schemes": ["HTTPS"],
+        "allowed_domains": ["*.example.com", "api.weather.org"],
+        "max_url_length": 64,
+    });
+    let unauthorized = caller
+        .call(fetcher.id(), "set_url_policy")
+        .args_json(json!({ "url_policy": policy }))
+        .transact()
+        .await?;
+    assert!(
+        unauthorized.is_failure(),
+        "only the owner may set the URL policy"
+    );
+
+    let updated = fetcher
+        .call("set_url_policy")
+        .args_json(json!({ "url_policy": policy }))
+        .transact()
+        .await?
+        .into_result()?;
+    assert!(updated
+        .logs()
+        .iter()
+        .any(|log| log.contains("url_policy_updated")));
+
+    let stored: UrlPolicyView = fetcher
+        .view("get_url_policy")
+        .args_json(json!({}))
+        .await?
+        .json()?;
+    assert_eq!(stored.allowed_schemes, vec!["https"]);
+    assert_eq!(
+        stored.allowed_domains,
+        vec!["*.example.com", "api.weather.org"]
+    );
+    assert_eq!(stored.max_url_length, 64);
+
+    let long_url = format!("https://api.example.com/{}", "a".repeat(64));
+    for rejected in [
+        "http://api.example.com/data",
+        "https://example.org/data",
+        "https://example.com/data",
+        "https://evil.org\\@api.example.com/",
+        "https://api.example.com.evil.org/",
+        "api.example.com/data",
+        long_url.as_str(),
+    ] {
+        let outcome = caller
+            .call(fetcher.id(), "fetch")
+            .args_json(json!({ "url": rejected, "context": null }))
+            .deposit(FETCH_DEPOSIT)
+            .max_gas()
+            .transact()
+            .await?;
+        assert!(outcome.is_failure(), "{rejected} should be rejected");
+    }
+
+    let fetch_tx = caller
+        .call(fetcher.id(), "fetch")
+        .args_json(json!({ "url": "https://user@API.example.com:8443/data", "context": null }))
+        .deposit(FETCH_DEPOSIT)
+        .max_gas()
+        .transact_async()
+        .await?;
+
+    let pending: PendingRequestView = common::wait_for_pending(&fetcher).await?;
+    assert_eq!(pending.url, "https://user@API.example.com:8443/data");
+
+    caller
+        .call(fetcher.id(), "cancel")
+        .args_json(json!({ "request_id": pending.request_id }))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+    let fetch_result: FetchResultView = fetch_tx.await?.json()?;
+    assert!(matches!(fetch_result.status, FetchStatusView::Cancelled));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn extraction_spec_and_outcome_are_reported() -> Result<()> {
+    let (worker, fetcher, relayer) = common::setup().await?;
+    let caller = worker.dev_create_account().await?;
+
+    let invalid = caller
+        .call(fetcher.id(), "fetch")
+        .args_json(json!({
+            "url": "https://example.com/weather",
+            "context": null,
+            "options": { "extract": ["main/temp"] },
+        }))
+        .deposit(FETCH_DEPOSIT)
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(
+        invalid.is_failure(),
+        "extract entries must be JSON Pointers"
+    );
+
+    let fetch_tx = caller
+        .call(fetcher.id(), "fetch")
+        .args_json(json!({
+            "url": "https://example.com/weather",
+            "context": null,
+            "options": { "extract": ["/main/temp", "/name"] },
+        }))
+        .deposit(FETCH_DEPOSIT)
+        .max_gas()
+        .transact_async()
+        .await?;
+
+    let pending: PendingRequestView = common::wait_for_pending(&fetcher).await?;
+    assert_eq!(
+        pending.extract,
+        Some(vec!["/main/temp".to_string(), "/name".to_string()])
+    );
+
+    let body = br#"{"/main/temp":21.5,"/name":null}"#.to_vec();
+    let respond_args = json!({
+        "request_id": pending.request_id,
+        "yield_id": pending.yield_id,
+        "body": body,
+        "status_code": 200,
+    });
+    let unreported = relayer
+        .call(fetcher.id(), "respond")
+        .args_json(respond_args.clone())
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(
+        unreported.is_failure(),
+        "extract requests need an extraction outcome"
+    );
+
+    let mut respond_args = respond_args;
+    respond_args["extraction"] = json!({ "Extracted": { "missing": ["/name"] } });
+    relayer
+        .call(fetcher.id(), "respond")
+        .args_json(respond_args)
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+
+    let fetch_result: FetchResultView = fetch_tx.await?.json()?;
+    assert!(matches!(fetch_result.status, FetchStatusView::Completed));
+    assert_eq!(fetch_result.body, Some(body));
+    assert_eq!(
+        fetch_result.extract,
+        Some(vec!["/main/temp".to_string(), "/name".to_string()])
+    );
+    assert_eq!(
+        fetch_result.extraction,
+        Some(json!({ "Extracted": { "missing": ["/name"] } }))
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn gzip_uploads_are_delivered_in_the_requested_encoding() -> Result<()> {
+    let (worker, fetcher, relayer) = common::setup().await?;
+    let caller = worker.dev_create_account().await?;
+
+    let body = br#"{"temperature":21.5,"city":"Barcelona"}"#.repeat(100);
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&body)?;
+    let compressed = encoder.finish()?;
+
+    let fetch_tx = caller
+        .call(fetcher.id(), "fetch")
+        .args_json(json!({
+            "url": "https://example.com/large.json",
+            "context": null,
+            "options": { "response_encoding": "Gzip" },
+        }))
+        .deposit(FETCH_DEPOSIT)
+        .max_gas()
+        .transact_async()
+        .await?;
+
+    let pending: PendingRequestView = common::wait_for_pending(&fetcher).await?;
+
+    relayer
+        .call(fetcher.id(), "store_response_chunk_base64")
+        .args_json(json!({
+            "request_id": pending.request_id,
+            "data": BASE64.encode(&compressed),
+            "offset": 0,
+        }))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+    relayer
+        .call(fetcher.id(), "respond")
+        .args_json(json!({
+            "request_id": pending.request_id,
+            "yield_id": pending.yield_id,
+            "status_code": 200,
+            "body_hash": Sha256::digest(&compressed).to_vec(),
+            "body_encoding": "Gzip",
+        }))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+
+    let fetch_result: FetchResultView = fetch_tx.await?.json()?;
+    assert!(matches!(fetch_result.status, FetchStatusView::Completed));
+    assert_eq!(fetch_result.body.as_ref(), Some(&compressed));
+    assert_eq!(fetch_result.body_encoding.as_deref(), Some("Gzip"));
+    assert_eq!(
+        fetch_result.body_hash,
+        Some(Sha256::digest(&compressed).to_vec())
+    );
+
+    // The contract doesn't inflate bodies in the callback, so a request that wants the body
+    // delivered plain only takes a plain upload.
+    let fetch_tx = caller
+        .call(fetcher.id(), "fetch")
+        .args_json(json!({
+            "url": "https://example.com/large.json",
+            "context": null,
+            "options": { "response_encoding": "Identity" },
+        }))
+        .deposit(FETCH_DEPOSIT)
+        .max_gas()
+        .transact_async()
+        .await?;
+    let pending: PendingRequestView = common::wait_for_pending(&fetcher).await?;
+    let disallowed = relayer
+        .call(fetcher.id(), "respond")
+        .args_json(json!({
+            "request_id": pending.request_id,
+            "yield_id": pending.yield_id,
+            "body": compressed,
+            "body_encoding": "Gzip",
+        }))
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(
+        disallowed.is_failure(),
+        "gzip bodies need response_encoding Gzip on the request"
+    );
+    relayer
+        .call(fetcher.id(), "respond")
+        .args_json(json!({
+            "request_id": pending.request_id,
+            "yield_id": pending.yield_id,
+            "body": body,
+            "status_code": 200,
+        }))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+    let fetch_result: FetchResultView = fetch_tx.await?.json()?;
+    assert!(matches!(fetch_result.status, FetchStatusView::Completed));
+    assert_eq!(fetch_result.body, Some(body));
+    assert_eq!(fetch_result.body_encoding.as_deref(), Some("Identity"));
+
+    // The same goes for a request that leaves `response_encoding` at its default.
+    let fetch_tx = caller
+        .call(fetcher.id(), "fetch")
+        .args_json(json!({ "url": "https://example.com/plain", "context": null }))
+        .deposit(FETCH_DEPOSIT)
+        .max_gas()
+        .transact_async()
+        .await?;
+    let pending: PendingRequestView = common::wait_for_pending(&fetcher).await?;
+    let disallowed = relayer
+        .call(fetcher.id(), "respond")
+        .args_json(json!({
+            "request_id": pending.request_id,
+            "yield_id": pending.yield_id,
+            "body": compressed,
+            "body_encoding": "Gzip",
+        }))
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(
+        disallowed.is_failure(),
+        "gzip bodies need response_encoding Gzip on the request"
+    );
+    caller
+        .call(fetcher.id(), "cancel")
+        .args_json(json!({ "request_id": pending.request_id }))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+    fetch_tx.await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn borsh_variants_store_and_respond_with_raw_bytes() -> Result<()> {
+    let (worker, fetcher, relayer) = common::setup().await?;
+    let caller = worker.dev_create_account().await?;
+
+    let fetch_tx = caller
+        .call(fetcher.id(), "fetch")
+        .args_json(json!({ "url": "https://example.com/binary", "context": null }))
+        .deposit(FETCH_DEPOSIT)
+        .max_gas()
+        .transact_async()
+        .await?;
+    let pending: PendingRequestView = common::wait_for_pending(&fetcher).await?;
+
+    let body: Vec<u8> = (0..=255u8).cycle().take(2048).collect();
+    let (first, rest) = body.split_at(1000);
+    for (data, offset) in [(first, 0u64), (rest, first.len() as u64)] {
+        relayer
+            .call(fetcher.id(), "store_response_chunk_borsh")
+            .args_borsh((pending.request_id, data.to_vec(), offset))
+            .max_gas()
+            .transact()
+            .await?
+            .into_result()?;
+    }
+
+    // Arguments in the order of `respond`'s parameters; the unused ones are `None`.
+    relayer
+        .call(fetcher.id(), "respond_borsh")
+        .args_borsh((
+            pending.request_id,
+            pending.yield_id.clone(),
+            None::<Vec<u8>>,
+            Some(200u16),
+            None::<Vec<(String, String)>>,
+            Some(Sha256::digest(&body).to_vec()),
+            Some(body.len() as u64),
+            None::<u8>,
+            None::<u8>,
+        ))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+
+    let fetch_result: FetchResultView = fetch_tx.await?.json()?;
+    assert!(matches!(fetch_result.status, FetchStatusView::Completed));
+    assert_eq!(fetch_result.status_code, Some(200));
+    assert_eq!(fetch_result.body, Some(body));
+
+    Ok(())
+}
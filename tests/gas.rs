@@ -0,0 +1,99 @@
+mod common;
+
+use anyhow::Result;
+use near_workspaces::types::{Gas, NearToken};
+use relayer::gas::{chunk_gas, respond_gas};
+use serde::Deserialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+#[derive(Deserialize)]
+struct PendingRequestView {
+    request_id: u64,
+    yield_id: Vec<u8>,
+}
+
+/// Uploads bodies of growing size with the gas `relayer::gas` estimates for each call and checks
+/// that every call succeeds without burning more than it was given. Run with `--nocapture` to
+/// see the measurements when recalibrating the model.
+#[tokio::test]
+async fn gas_model_covers_chunk_and_respond_calls() -> Result<()> {
+    let (worker, fetcher, relayer) = common::setup().await?;
+    let caller = worker.dev_create_account().await?;
+
+    // Body sizes, each uploaded in chunks of at most `chunk_size` bytes.
+    for (body_size, chunk_size) in [
+        (1_000, 1_000),
+        (100_000, 100_000),
+        (500_000, 500_000),
+        (1_000_000, 1_000_000),
+        (1_000_000, 500_000),
+        (1_000_000, 250_000),
+    ] {
+        let deposit: NearToken = fetcher
+            .view("estimate_deposit")
+            .args_json(json!({
+                "url": "https://example.com/large",
+                "expected_response_bytes": body_size,
+            }))
+            .await?
+            .json()?;
+        let fetch_tx = caller
+            .call(fetcher.id(), "fetch")
+            .args_json(json!({ "url": "https://example.com/large", "context": null }))
+            .deposit(deposit)
+            .max_gas()
+            .transact_async()
+            .await?;
+
+        let pending: PendingRequestView = common::wait_for_pending(&fetcher).await?;
+
+        let body: Vec<u8> = (0..body_size).map(|i| (i % 251) as u8).collect();
+        for (index, chunk) in body.chunks(chunk_size).enumerate() {
+            let offset = (index * chunk_size) as u64;
+            let estimate = chunk_gas(chunk.len() as u64);
+            let outcome = relayer
+                .call(fetcher.id(), "store_response_chunk_borsh")
+                .args_borsh((pending.request_id, chunk.to_vec(), offset))
+                .gas(Gas::from_gas(estimate.as_gas()))
+                .transact()
+                .await?;
+            let burnt = outcome.total_gas_burnt;
+            outcome.into_result()?;
+            println!(
+                "chunk of {} bytes at {offset}: burnt {burnt}, estimated {estimate}",
+                chunk.len()
+            );
+            assert!(burnt.as_gas() <= estimate.as_gas());
+        }
+
+        let estimate = respond_gas(body.len() as u64, 0);
+        let outcome = relayer
+            .call(fetcher.id(), "respond_borsh")
+            .args_borsh((
+                pending.request_id,
+                pending.yield_id.clone(),
+                None::<Vec<u8>>,
+                Some(200u16),
+                None::<Vec<(String, String)>>,
+                Some(Sha256::digest(&body).to_vec()),
+                Some(body.len() as u64),
+                None::<u8>,
+                None::<u8>,
+            ))
+            .gas(Gas::from_gas(estimate.as_gas()))
+            .transact()
+            .await?;
+        let burnt = outcome.total_gas_burnt;
+        outcome.into_result()?;
+        println!(
+            "respond for {} bytes: burnt {burnt}, estimated {estimate}",
+            body.len()
+        );
+        assert!(burnt.as_gas() <= estimate.as_gas());
+
+        fetch_tx.await?.into_result()?;
+    }
+
+    Ok(())
+}
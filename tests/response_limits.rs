@@ -0,0 +1,178 @@
+mod common;
+
+use std::io::Read;
+
+use anyhow::Result;
+use flate2::read::GzDecoder;
+use near_workspaces::types::NearToken;
+use serde::Deserialize;
+use serde_json::json;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+#[derive(Deserialize)]
+struct FetchResultView {
+    status: serde_json::Value,
+    #[serde(default)]
+    body: Option<Vec<u8>>,
+    #[serde(default)]
+    body_encoding: Option<String>,
+}
+
+/// Serve an endless body without a Content-Length, so only the streaming cap can stop it.
+async fn serve_endless_body(listener: TcpListener) {
+    while let Ok((mut socket, _)) = listener.accept().await {
+        tokio::spawn(async move {
+            let mut request = [0u8; 4096];
+            let _ = socket.read(&mut request).await;
+            let header = "HTTP/1.1 200 OK\r\ncontent-type: text/plain\r\nconnection: close\r\n\r\n";
+            if socket.write_all(header.as_bytes()).await.is_err() {
+                return;
+            }
+            let chunk = [b'x'; 16 * 1024];
+            while socket.write_all(&chunk).await.is_ok() {}
+        });
+    }
+}
+
+#[tokio::test]
+async fn oversized_responses_fail_with_response_too_large() -> Result<()> {
+    let (worker, fetcher, relayer) = common::setup().await?;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let upstream = format!("http://{}/large", listener.local_addr()?);
+    tokio::spawn(serve_endless_body(listener));
+
+    let relayer_config = common::relayer_config(&worker, &fetcher, &relayer)?;
+
+    let fetch_tx = fetcher
+        .call("fetch")
+        .args_json(json!({
+            "url": upstream,
+            "context": null,
+            "options": { "max_response_bytes": 100_000 },
+        }))
+        .deposit(NearToken::from_millinear(100))
+        .max_gas()
+        .transact_async()
+        .await?;
+
+    common::process_pending(&relayer_config).await?;
+
+    let fetch_result: FetchResultView = fetch_tx.await?.json()?;
+    assert_eq!(fetch_result.status["Failed"]["kind"], "ResponseTooLarge");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn responses_the_deposit_cannot_store_fail_with_insufficient_deposit() -> Result<()> {
+    let (worker, fetcher, relayer) = common::setup().await?;
+
+    let upstream = common::spawn_upstream(vec![b'0'; 200_000]).await?;
+    let relayer_config = common::relayer_config(&worker, &fetcher, &relayer)?;
+
+    // Only enough deposit for a 1 KB response.
+    let deposit: NearToken = fetcher
+        .view("estimate_deposit")
+        .args_json(json!({ "url": upstream, "expected_response_bytes": 1_000 }))
+        .await?
+        .json()?;
+    let fetch_tx = fetcher
+        .call("fetch")
+        .args_json(json!({ "url": upstream, "context": null }))
+        .deposit(deposit)
+        .max_gas()
+        .transact_async()
+        .await?;
+
+    common::process_pending(&relayer_config).await?;
+
+    let fetch_result: FetchResultView = fetch_tx.await?.json()?;
+    assert_eq!(fetch_result.status["Failed"]["kind"], "InsufficientDeposit");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn bodies_over_the_contract_cap_fail_with_response_too_large() -> Result<()> {
+    let (worker, fetcher, relayer) = common::setup().await?;
+
+    // Within the relayer's default 5 MB download limit, but twice what the contract stores.
+    let upstream = common::spawn_upstream(vec![b'0'; 2_000_000]).await?;
+
+    let relayer_config = common::relayer_config(&worker, &fetcher, &relayer)?;
+
+    let deposit: NearToken = fetcher
+        .view("estimate_deposit")
+        .args_json(json!({ "url": upstream, "expected_response_bytes": 1_000_000 }))
+        .await?
+        .json()?;
+    let fetch_tx = fetcher
+        .call("fetch")
+        .args_json(json!({ "url": upstream, "context": null }))
+        .deposit(deposit)
+        .max_gas()
+        .transact_async()
+        .await?;
+
+    common::process_pending(&relayer_config).await?;
+
+    let fetch_result: FetchResultView = fetch_tx.await?.json()?;
+    assert_eq!(fetch_result.status["Failed"]["kind"], "ResponseTooLarge");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn near_limit_gzip_bodies_are_never_inflated_on_chain() -> Result<()> {
+    let (worker, fetcher, relayer) = common::setup().await?;
+
+    // Compresses to a few KB, but inflating it in the callback would blow its gas.
+    let body_len = 900_000;
+    let upstream = common::spawn_upstream(vec![b'0'; body_len]).await?;
+
+    let relayer_config = common::relayer_config(&worker, &fetcher, &relayer)?;
+
+    let deposit: NearToken = fetcher
+        .view("estimate_deposit")
+        .args_json(json!({ "url": upstream, "expected_response_bytes": body_len }))
+        .await?
+        .json()?;
+
+    for response_encoding in ["Identity", "Gzip"] {
+        let fetch_tx = fetcher
+            .call("fetch")
+            .args_json(json!({
+                "url": upstream,
+                "context": null,
+                "options": { "response_encoding": response_encoding },
+            }))
+            .deposit(deposit)
+            .max_gas()
+            .transact_async()
+            .await?;
+
+        common::process_pending(&relayer_config).await?;
+
+        // The relayer only compresses when the body may be delivered compressed.
+        let fetch_result: FetchResultView = fetch_tx.await?.json()?;
+        assert_eq!(fetch_result.status, json!("Completed"));
+        assert_eq!(
+            fetch_result.body_encoding.as_deref(),
+            Some(response_encoding)
+        );
+        let body = fetch_result.body.unwrap_or_default();
+        let body = if response_encoding == "Gzip" {
+            assert!(body.len() < body_len);
+            let mut inflated = Vec::new();
+            GzDecoder::new(body.as_slice()).read_to_end(&mut inflated)?;
+            inflated
+        } else {
+            body
+        };
+        assert_eq!(body, vec![b'0'; body_len]);
+    }
+
+    Ok(())
+}
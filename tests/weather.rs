@@ -1,26 +1,21 @@
+mod common;
+
 use anyhow::Result;
-use near_workspaces::network::NetworkInfo;
-use relayer::{process_once, Config as RelayerConfig};
+use near_workspaces::types::NearToken;
+use relayer::policy::HostPattern;
+use relayer::secrets::{Secret, SecretStore};
 use serde_json::json;
-use tokio::time::{sleep, Duration};
 
 #[tokio::test]
 async fn weather_contract_flow() -> Result<()> {
-    let fetcher_wasm = near_workspaces::compile_project("./").await?;
-    let weather_wasm = near_workspaces::compile_project("./examples/weather").await?;
-
-    let worker = near_workspaces::sandbox().await?;
+    // The example calls the real OpenWeather API, which needs a key of your own.
+    let Ok(api_key) = std::env::var("OPENWEATHER_API_KEY") else {
+        eprintln!("OPENWEATHER_API_KEY is not set, skipping weather_contract_flow");
+        return Ok(());
+    };
 
-    let relayer = worker.dev_create_account().await?;
-    let rpc_url = worker.info().rpc_url.to_string();
-
-    let fetcher = worker.dev_deploy(&fetcher_wasm).await?;
-    fetcher
-        .call("new")
-        .args_json(json!({ "trusted_relayer": relayer.id() }))
-        .transact()
-        .await?
-        .into_result()?;
+    let (worker, fetcher, relayer) = common::setup().await?;
+    let weather_wasm = near_workspaces::compile_project("./examples/weather").await?;
 
     let weather = worker.dev_deploy(&weather_wasm).await?;
     weather
@@ -35,28 +30,21 @@ async fn weather_contract_flow() -> Result<()> {
     let request_future = weather
         .call("request_weather")
         .args_json(json!({ "city": city }))
+        .deposit(NearToken::from_millinear(100))
         .max_gas()
         .transact_async()
         .await?;
 
-    let relayer_config = RelayerConfig::from_parts(
-        &rpc_url,
-        fetcher.id().as_str(),
-        relayer.id().as_str(),
-        &relayer.secret_key().to_string(),
-        Some(1),
-    )?;
-    let http_client = relayer_config.http_client()?;
-
-    let mut processed = false;
-    for _ in 0..10 {
-        if process_once(&relayer_config, &http_client).await? {
-            processed = true;
-            break;
-        }
-        sleep(Duration::from_millis(200)).await;
-    }
-    assert!(processed, "relayer did not process pending request");
+    // The example requests reference the API key as {{secret:openweather}}; the relayer only
+    // fills it in for the weather contract.
+    let openweather = Secret {
+        value: api_key,
+        allowed_callers: vec![weather.id().to_string()],
+        allowed_hosts: vec![HostPattern::new("api.openweathermap.org")],
+    };
+    let relayer_config = common::relayer_config(&worker, &fetcher, &relayer)?
+        .with_secrets(SecretStore::default().with_secret("openweather", openweather));
+    common::process_pending(&relayer_config).await?;
 
     let request_result: String = request_future.await?.json()?;
     assert!(!request_result.is_empty(), "weather contract reported failure");